@@ -0,0 +1,106 @@
+use crate::{
+    config::ClientRateLimitSettings,
+    handlers, handlers_admin, handlers_v1, health_check, health_status,
+    middleware::{Authenticate, RateLimit, RecordMetrics},
+    registry::ServiceRegistry,
+};
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    web, App, Error,
+};
+use std::sync::Arc;
+
+/// The full application: routes, and the middleware around each scope in the
+/// order it runs. Called once per worker; limiters and key stores are shared
+/// through the registry.
+pub fn build_app(
+    registry: web::Data<ServiceRegistry>,
+    client_limits: &ClientRateLimitSettings,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Response = ServiceResponse<BoxBody>,
+        Config = (),
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    // Per-client limits for each API scope
+    let v1_rate_limit = RateLimit::new(registry.client_rate_limiter("/v1"), client_limits);
+    let currency_rate_limit = RateLimit::new(registry.client_rate_limiter("/currency"), client_limits);
+    let v1_auth = Authenticate::new(registry.key_store.clone(), "/v1");
+    let admin_auth = Authenticate::new(Some(registry.admin_key_store.clone()), "/admin");
+    let currency_auth = Authenticate::new(registry.key_store.clone(), "/currency");
+    let record_metrics = RecordMetrics::new(Arc::clone(&registry.monitor));
+
+    App::new()
+        // Add registry
+        .app_data(registry)
+        .wrap(record_metrics)
+
+        // Health check endpoints
+        .service(
+            web::resource("/health")
+                .route(web::get().to(health_status))
+        )
+        .service(
+            web::resource("/health/live")
+                .route(web::get().to(health_check))
+        )
+
+        // Prometheus scrape endpoint
+        .service(
+            web::resource("/metrics")
+                .route(web::get().to(handlers_admin::metrics))
+        )
+
+        // API v1 routes
+        .service(
+            web::scope("/v1")
+                .wrap(v1_rate_limit)
+                .wrap(v1_auth)
+                .configure(configure_v1_routes)
+        )
+
+        // Operational endpoints
+        .service(
+            web::scope("/admin")
+                .wrap(admin_auth)
+                .configure(configure_admin_routes)
+        )
+
+        // Legacy routes (without version prefix)
+        .service(
+            web::resource("/currency")
+                .wrap(currency_rate_limit)
+                .wrap(currency_auth)
+                .route(web::post().to(handlers::convert_currency))
+        )
+}
+
+fn configure_v1_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/currency")
+            .route("", web::post().to(handlers_v1::convert_currency))
+    );
+}
+
+fn configure_admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/cache")
+            .route(web::get().to(handlers_admin::cache_stats))
+    )
+    .service(
+        web::resource("/stats")
+            .route(web::get().to(handlers_admin::usage_stats))
+    )
+    .service(
+        web::resource("/stats/reset")
+            .route(web::post().to(handlers_admin::reset_usage_stats))
+    )
+    .service(
+        web::resource("/quota")
+            .route(web::get().to(handlers_admin::quota_status))
+    );
+}
@@ -0,0 +1,269 @@
+use async_trait::async_trait;
+use crate::errors::ServiceError;
+use crate::rate_limit::{LimitRule, RateLimiter};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// What a tenant's API key entitles it to.
+#[derive(Debug)]
+pub struct Tier {
+    pub name: String,
+    /// Route scopes the tier may call, e.g. `/currency`, `/v1` or `/admin`
+    pub endpoints: Vec<String>,
+    /// Whether `/v1` answers with the detailed response or the simple one
+    pub detailed_responses: bool,
+    /// Replaces the scope's per-client limits for the tier's tenants, when set
+    pub limiter: Option<Arc<RateLimiter>>,
+}
+
+impl Tier {
+    pub fn allows(&self, scope: &str) -> bool {
+        self.endpoints.iter().any(|endpoint| endpoint == scope)
+    }
+}
+
+/// The owner of an API key, added to the request extensions once authenticated.
+#[derive(Debug, Clone)]
+pub struct Tenant {
+    pub id: String,
+    pub tier: Arc<Tier>,
+}
+
+/// Resolves API keys to tenants.
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// The tenant owning `api_key`, or `None` for an unknown key.
+    async fn tenant(&self, api_key: &str) -> Result<Option<Tenant>, ServiceError>;
+}
+
+fn default_endpoints() -> Vec<String> {
+    vec!["/currency".to_string(), "/v1".to_string()]
+}
+
+fn default_detailed_responses() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct TierFile {
+    #[serde(default)]
+    rate_limits: Vec<LimitRule>,
+    #[serde(default = "default_endpoints")]
+    endpoints: Vec<String>,
+    #[serde(default = "default_detailed_responses")]
+    detailed_responses: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyFileEntry {
+    tenant: String,
+    tier: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyFile {
+    tiers: HashMap<String, TierFile>,
+    keys: HashMap<String, KeyFileEntry>,
+}
+
+/// API keys read once from a JSON file of tiers and keys:
+///
+/// ```json
+/// {
+///   "tiers": {
+///     "free": { "rate_limits": ["bucket:5@1/s", "200/1d"], "endpoints": ["/currency"] },
+///     "pro": { "rate_limits": ["bucket:50@10/s"], "endpoints": ["/currency", "/v1"] }
+///   },
+///   "keys": { "3f9c0d2e": { "tenant": "acme", "tier": "pro" } }
+/// }
+/// ```
+pub struct FileKeyStore {
+    tiers: HashMap<String, Arc<Tier>>,
+    tenants: HashMap<String, Tenant>,
+}
+
+impl FileKeyStore {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ServiceError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            ServiceError::ConfigError(format!("Failed to read API keys from {}: {}", path.display(), e))
+        })?;
+        Self::from_json(&contents)
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, ServiceError> {
+        let file: KeyFile = serde_json::from_str(contents)
+            .map_err(|e| ServiceError::ConfigError(format!("Invalid API key file: {}", e)))?;
+
+        let tiers: HashMap<String, Arc<Tier>> = file
+            .tiers
+            .into_iter()
+            .map(|(name, tier)| {
+                let limiter = (!tier.rate_limits.is_empty())
+                    .then(|| Arc::new(RateLimiter::with_rules(tier.rate_limits)));
+                let tier = Tier {
+                    name: name.clone(),
+                    endpoints: tier.endpoints,
+                    detailed_responses: tier.detailed_responses,
+                    limiter,
+                };
+                (name, Arc::new(tier))
+            })
+            .collect();
+
+        let tenants = file
+            .keys
+            .into_iter()
+            .map(|(api_key, entry)| {
+                let tier = tiers.get(&entry.tier).ok_or_else(|| {
+                    ServiceError::ConfigError(format!("API key for {} uses unknown tier {}", entry.tenant, entry.tier))
+                })?;
+                let tenant = Tenant {
+                    id: entry.tenant,
+                    tier: Arc::clone(tier),
+                };
+                Ok((api_key, tenant))
+            })
+            .collect::<Result<_, ServiceError>>()?;
+
+        Ok(Self { tiers, tenants })
+    }
+
+    pub fn len(&self) -> usize {
+        self.tenants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    /// Every tier in the file, including those no key uses.
+    pub fn tiers(&self) -> impl Iterator<Item = &Arc<Tier>> {
+        self.tiers.values()
+    }
+}
+
+#[async_trait]
+impl KeyStore for FileKeyStore {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn tenant(&self, api_key: &str) -> Result<Option<Tenant>, ServiceError> {
+        Ok(self.tenants.get(api_key).cloned())
+    }
+}
+
+/// Tier of the operator key, which only opens the admin endpoints.
+pub const ADMIN_TIER: &str = "admin";
+
+/// Accepts a single operator key for `/admin`, and every client key from an
+/// optional key store whose tier includes it. With neither, every key is refused.
+pub struct AdminKeyStore {
+    api_key: Option<String>,
+    operator: Tenant,
+    clients: Option<Arc<dyn KeyStore>>,
+}
+
+impl AdminKeyStore {
+    pub fn new(api_key: impl Into<String>, clients: Option<Arc<dyn KeyStore>>) -> Self {
+        Self {
+            api_key: Some(api_key.into()),
+            ..Self::without_operator(clients)
+        }
+    }
+
+    /// Admits client keys whose tier includes `/admin`, and no operator key.
+    pub fn without_operator(clients: Option<Arc<dyn KeyStore>>) -> Self {
+        let tier = Tier {
+            name: ADMIN_TIER.to_string(),
+            endpoints: vec!["/admin".to_string()],
+            detailed_responses: true,
+            limiter: None,
+        };
+        Self {
+            api_key: None,
+            operator: Tenant {
+                id: ADMIN_TIER.to_string(),
+                tier: Arc::new(tier),
+            },
+            clients,
+        }
+    }
+
+    /// Compares in constant time, so response timing does not leak the key.
+    fn is_operator_key(&self, api_key: &str) -> bool {
+        let Some(operator_key) = &self.api_key else { return false };
+        api_key.len() == operator_key.len()
+            && api_key
+                .bytes()
+                .zip(operator_key.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+#[async_trait]
+impl KeyStore for AdminKeyStore {
+    fn name(&self) -> &'static str {
+        "admin"
+    }
+
+    async fn tenant(&self, api_key: &str) -> Result<Option<Tenant>, ServiceError> {
+        if self.is_operator_key(api_key) {
+            return Ok(Some(self.operator.clone()));
+        }
+        match &self.clients {
+            Some(clients) => clients.tenant(api_key).await,
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_key_store() {
+        let store = FileKeyStore::from_json(
+            r#"{
+                "tiers": {
+                    "free": { "rate_limits": ["10/1d"], "endpoints": ["/currency"], "detailed_responses": false },
+                    "pro": {}
+                },
+                "keys": {
+                    "free-key": { "tenant": "hobbyist", "tier": "free" },
+                    "pro-key": { "tenant": "acme", "tier": "pro" }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(store.len(), 2);
+
+        let free = store.tenant("free-key").await.unwrap().unwrap();
+        assert_eq!((free.id.as_str(), free.tier.name.as_str()), ("hobbyist", "free"));
+        assert!(free.tier.allows("/currency") && !free.tier.allows("/v1"));
+        assert!(!free.tier.detailed_responses);
+        assert_eq!(free.tier.limiter.as_ref().unwrap().rules(), &[LimitRule::per_window(10, chrono::Duration::days(1))]);
+
+        // Tier settings default to the public endpoints with full responses
+        let pro = store.tenant("pro-key").await.unwrap().unwrap();
+        assert!(pro.tier.allows("/v1") && pro.tier.detailed_responses && pro.tier.limiter.is_none());
+        assert!(store.tenant("unknown").await.unwrap().is_none());
+
+        let admin = AdminKeyStore::new("operator-key", Some(Arc::new(store)));
+        assert!(admin.tenant("operator-key").await.unwrap().unwrap().tier.allows("/admin"));
+        assert_eq!(admin.tenant("pro-key").await.unwrap().unwrap().id, "acme");
+        assert!(admin.tenant("operator-kez").await.unwrap().is_none());
+        assert!(AdminKeyStore::without_operator(None).tenant("operator-key").await.unwrap().is_none());
+
+        let unknown_tier = r#"{ "tiers": {}, "keys": { "k": { "tenant": "t", "tier": "gold" } } }"#;
+        assert!(matches!(FileKeyStore::from_json(unknown_tier), Err(ServiceError::ConfigError(_))));
+    }
+}
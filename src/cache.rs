@@ -1,68 +1,565 @@
 use std::collections::HashMap;
+use std::str::FromStr;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use chrono::{DateTime, Duration, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use crate::cache_backend::{CacheBackend, MemoryBackend, WriteOutcome};
+use crate::errors::ServiceError;
+use crate::models::ExchangeRateResponse;
 
-#[derive(Clone, Debug)]
-struct CacheEntry<T> {
-    data: T,
-    expires_at: DateTime<Utc>,
+/// Which entry makes room when a full cache receives a new key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used entry
+    #[default]
+    Lru,
+    /// Evict the least frequently used entry, oldest access first on ties
+    Lfu,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "lru" => Ok(Self::Lru),
+            "lfu" => Ok(Self::Lfu),
+            other => Err(format!("Unknown cache eviction policy: {}", other)),
+        }
+    }
+}
+
+/// Whether a cached value is within its TTL or only within the stale window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    Fresh,
+    Stale,
+}
+
+/// A cached value together with how fresh and how old it is.
+#[derive(Debug, Clone)]
+pub struct CachedValue<T> {
+    pub data: T,
+    pub freshness: Freshness,
+    pub age: Duration,
+}
+
+impl<T> CachedValue<T> {
+    pub fn is_stale(&self) -> bool {
+        self.freshness == Freshness::Stale
+    }
+}
+
+/// A cache entry with its original horizons, as kept by a backend and
+/// written to and read from a snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotEntry<T> {
+    pub key: String,
+    pub data: T,
+    pub inserted_at: DateTime<Utc>,
+    /// End of the fresh horizon
+    pub expires_at: DateTime<Utc>,
+    /// End of the stale-usable horizon; the entry is dropped afterwards
+    pub stale_until: DateTime<Utc>,
+}
+
+/// Point-in-time counters and occupancy of one cache.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Backend holding the entries, "memory" or "redis"
+    pub backend: String,
+    /// Lookups answered with a fresh entry
+    pub hits: u64,
+    /// Lookups answered with an entry past its TTL but within the stale window
+    pub stale_hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    /// Inserts dropped because the cache has no room at all
+    pub rejected_inserts: u64,
+    pub evictions: u64,
+    /// Entries dropped after their stale window ran out
+    pub expirations: u64,
+    /// Backend calls that failed and were treated as misses
+    pub backend_errors: u64,
+    pub size: usize,
+    /// Unset for backends that leave the size limit to the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<usize>,
+    /// Entries currently past their TTL, included in `size`
+    pub stale_entries: usize,
+    pub age_distribution: AgeDistribution,
+}
+
+/// Number of stored entries by time since insertion.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AgeDistribution {
+    pub under_1m: usize,
+    pub under_5m: usize,
+    pub under_15m: usize,
+    pub under_1h: usize,
+    pub under_1d: usize,
+    pub older: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oldest_seconds: Option<u64>,
+}
+
+impl AgeDistribution {
+    fn record(&mut self, age: Duration) {
+        let seconds = age.num_seconds().max(0) as u64;
+        let bucket = match seconds {
+            0..=59 => &mut self.under_1m,
+            60..=299 => &mut self.under_5m,
+            300..=899 => &mut self.under_15m,
+            900..=3599 => &mut self.under_1h,
+            3600..=86399 => &mut self.under_1d,
+            _ => &mut self.older,
+        };
+        *bucket += 1;
+        self.oldest_seconds = Some(self.oldest_seconds.map_or(seconds, |oldest| oldest.max(seconds)));
+    }
+}
+
+/// Anything that can report `CacheStats`, so caches of different value types
+/// can be listed side by side.
+#[async_trait]
+pub trait CacheStatsSource: Send + Sync {
+    async fn stats(&self) -> CacheStats;
+
+    /// Counters and size only, without listing the entries; `stale_entries`
+    /// and `age_distribution` are left empty. Cheap enough to scrape often.
+    async fn counters(&self) -> CacheStats;
+}
+
+#[derive(Debug, Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    stale_hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+    rejected_inserts: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    backend_errors: AtomicU64,
+}
+
+impl CacheCounters {
+    fn bump(counter: &AtomicU64, by: u64) {
+        counter.fetch_add(by, Ordering::Relaxed);
+    }
 }
 
 pub struct Cache<T> {
-    store: Arc<RwLock<HashMap<String, CacheEntry<T>>>>,
+    backend: Arc<dyn CacheBackend<T>>,
     ttl: Duration,
+    stale_window: Duration,
     max_size: usize,
+    eviction_policy: EvictionPolicy,
+    // Set by `with_backend`; the eviction policy then no longer swaps the backend
+    custom_backend: bool,
+    counters: CacheCounters,
 }
 
 impl<T: Clone + Send + Sync + 'static> Cache<T> {
+    /// An in-memory cache holding at most `max_size` entries.
     pub fn new(ttl_minutes: i64, max_size: usize) -> Self {
         Self {
-            store: Arc::new(RwLock::new(HashMap::new())),
+            backend: Arc::new(MemoryBackend::new(max_size)),
             ttl: Duration::minutes(ttl_minutes),
+            stale_window: Duration::zero(),
             max_size,
+            eviction_policy: EvictionPolicy::default(),
+            custom_backend: false,
+            counters: CacheCounters::default(),
+        }
+    }
+
+    /// Evicts by this policy when the default in-memory backend is full. A
+    /// backend given to `with_backend` is kept, before or after this call,
+    /// and applies its own eviction.
+    pub fn with_eviction_policy(mut self, eviction_policy: EvictionPolicy) -> Self {
+        self.eviction_policy = eviction_policy;
+        if !self.custom_backend {
+            self.backend = Arc::new(MemoryBackend::new(self.max_size).with_eviction_policy(eviction_policy));
+        }
+        self
+    }
+
+    /// Stores entries in `backend`, e.g. a Redis server shared between replicas.
+    pub fn with_backend(mut self, backend: Arc<dyn CacheBackend<T>>) -> Self {
+        self.backend = backend;
+        self.custom_backend = true;
+        self
+    }
+
+    /// Keeps entries usable as stale data for this long after they expire.
+    pub fn with_stale_window(mut self, stale_window_minutes: i64) -> Self {
+        self.stale_window = Duration::minutes(stale_window_minutes.max(0));
+        self
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.eviction_policy
+    }
+
+    /// Number of entries evicted to make room for new keys.
+    pub fn evictions(&self) -> u64 {
+        self.counters.evictions.load(Ordering::Relaxed)
+    }
+
+    /// Counters since the cache was created, plus its current size and entry
+    /// ages. Lists every entry, which for Redis means a SCAN and MGET of the namespace.
+    pub async fn stats(&self) -> CacheStats {
+        let now = Utc::now();
+        let mut age_distribution = AgeDistribution::default();
+        let mut stale_entries = 0;
+        let entries = self.backend_call(self.backend.entries().await).unwrap_or_default();
+        for entry in &entries {
+            age_distribution.record(now - entry.inserted_at);
+            if entry.expires_at <= now {
+                stale_entries += 1;
+            }
+        }
+
+        CacheStats {
+            stale_entries,
+            age_distribution,
+            ..self.counter_stats(entries.len())
+        }
+    }
+
+    /// Counters since the cache was created and the backend's current size.
+    pub async fn counters(&self) -> CacheStats {
+        self.counter_stats(self.len().await)
+    }
+
+    fn counter_stats(&self, size: usize) -> CacheStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        CacheStats {
+            backend: self.backend.name().to_string(),
+            hits: load(&self.counters.hits),
+            stale_hits: load(&self.counters.stale_hits),
+            misses: load(&self.counters.misses),
+            inserts: load(&self.counters.inserts),
+            rejected_inserts: load(&self.counters.rejected_inserts),
+            evictions: load(&self.counters.evictions),
+            expirations: load(&self.counters.expirations),
+            backend_errors: load(&self.counters.backend_errors),
+            size,
+            max_size: self.backend.capacity(),
+            stale_entries: 0,
+            age_distribution: AgeDistribution::default(),
+        }
+    }
+
+    /// Unwraps a backend result, logging and counting failures. A failing
+    /// backend degrades to misses rather than failing requests.
+    fn backend_call<R>(&self, result: Result<R, ServiceError>) -> Option<R> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!("{} cache backend failed: {}", self.backend.name(), e);
+                CacheCounters::bump(&self.counters.backend_errors, 1);
+                None
+            }
+        }
+    }
+
+    fn record_write(&self, outcome: Option<WriteOutcome>) -> bool {
+        match outcome {
+            Some(WriteOutcome::Stored { evicted, expired }) => {
+                CacheCounters::bump(&self.counters.inserts, 1);
+                CacheCounters::bump(&self.counters.evictions, evicted);
+                CacheCounters::bump(&self.counters.expirations, expired);
+                true
+            }
+            Some(WriteOutcome::Rejected) => {
+                CacheCounters::bump(&self.counters.rejected_inserts, 1);
+                false
+            }
+            Some(WriteOutcome::Exists) | None => false,
         }
     }
 
     pub async fn get(&self, key: &str) -> Option<T> {
-        let store = self.store.read().await;
-        if let Some(entry) = store.get(key) {
-            if entry.expires_at > Utc::now() {
-                return Some(entry.data.clone());
+        let entry = self.backend_call(self.backend.get(key).await).flatten();
+        match entry.filter(|entry| entry.expires_at > Utc::now()) {
+            Some(entry) => {
+                CacheCounters::bump(&self.counters.hits, 1);
+                Some(entry.data)
+            }
+            None => {
+                CacheCounters::bump(&self.counters.misses, 1);
+                None
             }
         }
-        None
     }
 
-    pub async fn set(&self, key: String, value: T) {
-        let mut store = self.store.write().await;
-        if store.len() >= self.max_size {
-            return;
+    /// Returns a fresh or stale-usable entry, flagged with its freshness and age.
+    pub async fn lookup(&self, key: &str) -> Option<CachedValue<T>> {
+        let now = Utc::now();
+        let entry = self.backend_call(self.backend.get(key).await).flatten();
+        let Some(entry) = entry.filter(|entry| entry.stale_until > now) else {
+            CacheCounters::bump(&self.counters.misses, 1);
+            return None;
+        };
+        let freshness = if entry.expires_at > now { Freshness::Fresh } else { Freshness::Stale };
+        match freshness {
+            Freshness::Fresh => CacheCounters::bump(&self.counters.hits, 1),
+            Freshness::Stale => CacheCounters::bump(&self.counters.stale_hits, 1),
+        }
+        Some(CachedValue {
+            data: entry.data,
+            freshness,
+            age: now - entry.inserted_at,
+        })
+    }
+
+    /// Like `lookup`, without counting as a hit or miss or as an access to the entry.
+    pub async fn peek(&self, key: &str) -> Option<CachedValue<T>> {
+        let now = Utc::now();
+        let entry = self.backend_call(self.backend.peek(key).await).flatten()?;
+        if entry.stale_until <= now {
+            return None;
+        }
+        let freshness = if entry.expires_at > now { Freshness::Fresh } else { Freshness::Stale };
+        Some(CachedValue {
+            data: entry.data,
+            freshness,
+            age: now - entry.inserted_at,
+        })
+    }
+
+    /// Returns an entry even if it has expired, as long as it is within the stale window.
+    pub async fn get_stale(&self, key: &str) -> Option<T> {
+        self.lookup(key).await.map(|value| value.data)
+    }
+
+    /// Time left before the entry stops being fresh, negative once it has expired.
+    /// `None` when there is no entry, or it is past its stale window and can no longer be served.
+    pub async fn time_to_expiry(&self, key: &str) -> Option<Duration> {
+        let now = Utc::now();
+        let entry = self.backend_call(self.backend.peek(key).await).flatten()?;
+        if entry.stale_until <= now {
+            return None;
         }
-        
-        store.insert(
+        Some(entry.expires_at - now)
+    }
+
+    /// Snapshot of every unexpired entry.
+    pub async fn entries(&self) -> Vec<(String, T)> {
+        let now = Utc::now();
+        self.backend_call(self.backend.entries().await)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| (entry.key, entry.data))
+            .collect()
+    }
+
+    /// Keys of every entry that can still be served, fresh or stale.
+    pub async fn keys(&self) -> Vec<String> {
+        self.backend_call(self.backend.keys().await).unwrap_or_default()
+    }
+
+    pub async fn len(&self) -> usize {
+        self.backend_call(self.backend.len().await).unwrap_or(0)
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    pub async fn set(&self, key: String, value: T) {
+        let now = Utc::now();
+        let entry = SnapshotEntry {
             key,
-            CacheEntry {
-                data: value,
-                expires_at: Utc::now() + self.ttl,
-            },
-        );
+            data: value,
+            inserted_at: now,
+            expires_at: now + self.ttl,
+            stale_until: now + self.ttl + self.stale_window,
+        };
+        let outcome = self.backend_call(self.backend.put(entry).await);
+        self.record_write(outcome);
+    }
+
+    /// Copies every entry that is still usable, fresh or stale.
+    pub async fn snapshot(&self) -> Vec<SnapshotEntry<T>> {
+        self.backend_call(self.backend.entries().await).unwrap_or_default()
+    }
+
+    /// Loads snapshot entries, keeping their original expiry and skipping those
+    /// no longer usable. Existing keys win. Returns the number of entries restored.
+    pub async fn restore(&self, entries: Vec<SnapshotEntry<T>>) -> usize {
+        let now = Utc::now();
+        let mut restored = 0;
+        for entry in entries.into_iter().filter(|entry| entry.stale_until > now) {
+            let outcome = self.backend_call(self.backend.put_new(entry).await);
+            if self.record_write(outcome) {
+                restored += 1;
+            }
+        }
+        restored
     }
 
+    /// Drops entries that are no longer usable, keeping stale ones within their window.
     pub async fn clear_expired(&self) {
-        let mut store = self.store.write().await;
-        store.retain(|_, entry| entry.expires_at > Utc::now());
+        if let Some(expired) = self.backend_call(self.backend.purge_expired().await) {
+            CacheCounters::bump(&self.counters.expirations, expired as u64);
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Clone + Send + Sync + 'static> CacheStatsSource for Cache<T> {
+    async fn stats(&self) -> CacheStats {
+        Cache::stats(self).await
+    }
+
+    async fn counters(&self) -> CacheStats {
+        Cache::counters(self).await
     }
 }
 
-#[derive(Clone, Debug)]
+/// Full upstream rate table for one base currency, so every pair quoted
+/// against that base is served from a single upstream call.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ExchangeRateData {
-    pub rate: f64,
+    pub base: String,
+    pub rates: HashMap<String, f64>,
+    /// Upstream `time_last_update_utc`, or the fetch time when it is missing
     pub last_updated: DateTime<Utc>,
+    pub source: String,
 }
 
 impl ExchangeRateData {
     pub fn new_cache() -> Cache<Self> {
         Cache::new(60, 1000) // 60 minutes TTL, 1000 max entries
     }
-}
\ No newline at end of file
+
+    pub fn from_response(base: &str, response: ExchangeRateResponse) -> Self {
+        Self {
+            base: base.to_string(),
+            last_updated: response.last_updated().unwrap_or_else(Utc::now),
+            rates: response.conversion_rates,
+            source: response.source,
+        }
+    }
+
+    pub fn rate(&self, to_currency: &str) -> Option<f64> {
+        if to_currency == self.base {
+            return Some(1.0);
+        }
+        self.rates.get(to_currency).copied()
+    }
+
+    /// Cross rate between two other currencies quoted in this table,
+    /// e.g. JPY->BRL as USD->BRL / USD->JPY from a USD table.
+    pub fn cross_rate(&self, from_currency: &str, to_currency: &str) -> Option<f64> {
+        match (self.rate(from_currency), self.rate(to_currency)) {
+            (Some(from), Some(to)) if from > 0.0 => Some(to / from),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lru_evicts_least_recently_used() {
+        let cache = Cache::new(60, 2);
+        cache.set("a".to_string(), 1).await;
+        cache.set("b".to_string(), 2).await;
+        assert_eq!(cache.get("a").await, Some(1));
+
+        cache.set("c".to_string(), 3).await;
+        assert_eq!(cache.get("a").await, Some(1));
+        assert_eq!(cache.get("b").await, None);
+        assert_eq!(cache.get("c").await, Some(3));
+        assert_eq!(cache.evictions(), 1);
+
+        // Overwriting an existing key never evicts
+        cache.set("c".to_string(), 4).await;
+        assert_eq!(cache.len().await, 2);
+        assert_eq!(cache.evictions(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lfu_evicts_least_frequently_used() {
+        let cache = Cache::new(60, 2).with_eviction_policy(EvictionPolicy::Lfu);
+        cache.set("a".to_string(), 1).await;
+        cache.set("b".to_string(), 2).await;
+        for _ in 0..3 {
+            cache.get("a").await;
+        }
+        cache.get("b").await;
+        // "a" is older but more popular, so "b" goes
+        cache.set("c".to_string(), 3).await;
+        assert_eq!(cache.get("a").await, Some(1));
+        assert_eq!(cache.get("b").await, None);
+        assert_eq!(cache.evictions(), 1);
+
+        // A refreshed entry keeps its hits, so the popular "a" still survives
+        cache.set("a".to_string(), 10).await;
+        cache.get("c").await;
+        cache.set("d".to_string(), 4).await;
+        assert_eq!(cache.get("a").await, Some(10));
+        assert_eq!(cache.get("c").await, None);
+        assert_eq!(cache.evictions(), 2);
+
+        assert_eq!("LFU".parse::<EvictionPolicy>(), Ok(EvictionPolicy::Lfu));
+        assert!("fifo".parse::<EvictionPolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stale_window() {
+        let cache = Cache::new(0, 10).with_stale_window(5);
+        cache.set("a".to_string(), 1).await;
+
+        assert_eq!(cache.get("a").await, None);
+        let value = cache.lookup("a").await.unwrap();
+        assert!(value.is_stale());
+        assert_eq!(value.data, 1);
+
+        cache.clear_expired().await;
+        assert_eq!(cache.get_stale("a").await, Some(1));
+
+        // Snapshots keep the original horizons
+        let restored = Cache::new(60, 10);
+        assert_eq!(restored.restore(cache.snapshot().await).await, 1);
+        assert!(restored.lookup("a").await.unwrap().is_stale());
+
+        // Without a stale window an expired entry is gone
+        let cache = Cache::new(0, 10);
+        cache.set("a".to_string(), 1).await;
+        assert!(cache.lookup("a").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stats_counters() {
+        let cache = Cache::new(60, 1);
+        cache.set("a".to_string(), 1).await;
+        cache.get("a").await;
+        cache.get("missing").await;
+        cache.set("b".to_string(), 2).await;
+
+        let stats = cache.stats().await;
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!((stats.inserts, stats.evictions), (2, 1));
+        assert_eq!((stats.size, stats.max_size), (1, Some(1)));
+        assert_eq!(stats.age_distribution.under_1m, 1);
+
+        let expiring = Cache::new(0, 10);
+        expiring.set("a".to_string(), 1).await;
+        expiring.clear_expired().await;
+        assert_eq!(expiring.stats().await.expirations, 1);
+
+        let disabled = Cache::new(60, 0);
+        disabled.set("a".to_string(), 1).await;
+        assert_eq!(disabled.stats().await.rejected_inserts, 1);
+    }
+}
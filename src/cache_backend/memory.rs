@@ -0,0 +1,208 @@
+use async_trait::async_trait;
+use crate::cache::{EvictionPolicy, SnapshotEntry};
+use crate::cache_backend::{CacheBackend, WriteOutcome};
+use crate::errors::ServiceError;
+use chrono::{DateTime, Utc};
+use log::debug;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use tokio::sync::RwLock;
+
+/// Eviction order of the stored keys: hit count (LFU only), then last access.
+type Rank = (u64, u64);
+
+/// Keeps keys sorted by eviction rank, so a victim is found without scanning
+/// the store. Lives behind its own lock, so reads only need the shared store lock.
+#[derive(Debug, Default)]
+struct AccessIndex {
+    ranks: HashMap<String, Rank>,
+    order: BTreeSet<(Rank, String)>,
+    // Logical clock ordering accesses for LRU
+    clock: u64,
+    // No entry passes its stale window before this, so purging can wait until then
+    next_expiry: Option<DateTime<Utc>>,
+}
+
+impl AccessIndex {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Ranks a key that was just stored; an overwritten key keeps its rank.
+    fn insert(&mut self, key: &str, stale_until: DateTime<Utc>) {
+        self.next_expiry = Some(self.next_expiry.map_or(stale_until, |next| next.min(stale_until)));
+        if self.ranks.contains_key(key) {
+            return;
+        }
+        let rank = (0, self.tick());
+        self.ranks.insert(key.to_string(), rank);
+        self.order.insert((rank, key.to_string()));
+    }
+
+    fn touch(&mut self, key: &str, policy: EvictionPolicy) {
+        let tick = self.tick();
+        let Some(rank) = self.ranks.get_mut(key) else { return };
+        let old = *rank;
+        *rank = match policy {
+            EvictionPolicy::Lru => (0, tick),
+            EvictionPolicy::Lfu => (old.0 + 1, tick),
+        };
+        let new = *rank;
+        let key = key.to_string();
+        self.order.remove(&(old, key.clone()));
+        self.order.insert((new, key));
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(rank) = self.ranks.remove(key) {
+            self.order.remove(&(rank, key.to_string()));
+        }
+    }
+
+    fn victim(&self) -> Option<String> {
+        self.order.first().map(|(_, key)| key.clone())
+    }
+}
+
+/// Entries kept in a map inside this process, bounded by `max_size` and
+/// making room for new keys according to the eviction policy.
+pub struct MemoryBackend<T> {
+    store: RwLock<HashMap<String, SnapshotEntry<T>>>,
+    index: Mutex<AccessIndex>,
+    max_size: usize,
+    eviction_policy: EvictionPolicy,
+}
+
+impl<T: Clone + Send + Sync + 'static> MemoryBackend<T> {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            store: RwLock::new(HashMap::new()),
+            index: Mutex::new(AccessIndex::default()),
+            max_size,
+            eviction_policy: EvictionPolicy::default(),
+        }
+    }
+
+    pub fn with_eviction_policy(mut self, eviction_policy: EvictionPolicy) -> Self {
+        self.eviction_policy = eviction_policy;
+        self
+    }
+
+    fn index(&self) -> MutexGuard<'_, AccessIndex> {
+        self.index.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn purge(&self, store: &mut HashMap<String, SnapshotEntry<T>>) -> usize {
+        let now = Utc::now();
+        let mut index = self.index();
+        if index.next_expiry.is_none_or(|next| next > now) {
+            return 0;
+        }
+
+        let expired: Vec<String> = store
+            .iter()
+            .filter(|(_, entry)| entry.stale_until <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            store.remove(key);
+            index.remove(key);
+        }
+        index.next_expiry = store.values().map(|entry| entry.stale_until).min();
+        expired.len()
+    }
+}
+
+#[async_trait]
+impl<T: Clone + Send + Sync + 'static> CacheBackend<T> for MemoryBackend<T> {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(self.max_size)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<SnapshotEntry<T>>, ServiceError> {
+        let store = self.store.read().await;
+        let found = store.get(key).filter(|entry| entry.stale_until > Utc::now());
+        if found.is_some() {
+            self.index().touch(key, self.eviction_policy);
+        }
+        Ok(found.cloned())
+    }
+
+    async fn peek(&self, key: &str) -> Result<Option<SnapshotEntry<T>>, ServiceError> {
+        let store = self.store.read().await;
+        Ok(store.get(key).cloned())
+    }
+
+    async fn put(&self, entry: SnapshotEntry<T>) -> Result<WriteOutcome, ServiceError> {
+        if self.max_size == 0 {
+            return Ok(WriteOutcome::Rejected);
+        }
+
+        let mut store = self.store.write().await;
+        let (mut evicted, mut expired) = (0, 0);
+        if !store.contains_key(&entry.key) && store.len() >= self.max_size {
+            // Entries past their stale window go first, then the policy picks a victim
+            expired = self.purge(&mut store) as u64;
+            if store.len() >= self.max_size {
+                let mut index = self.index();
+                if let Some(victim) = index.victim() {
+                    debug!("Evicting cache entry {} ({:?})", victim, self.eviction_policy);
+                    store.remove(&victim);
+                    index.remove(&victim);
+                    evicted = 1;
+                }
+            }
+        }
+
+        self.index().insert(&entry.key, entry.stale_until);
+        store.insert(entry.key.clone(), entry);
+        Ok(WriteOutcome::Stored { evicted, expired })
+    }
+
+    async fn put_new(&self, entry: SnapshotEntry<T>) -> Result<WriteOutcome, ServiceError> {
+        let mut store = self.store.write().await;
+        if store.contains_key(&entry.key) {
+            return Ok(WriteOutcome::Exists);
+        }
+        if store.len() >= self.max_size {
+            return Ok(WriteOutcome::Rejected);
+        }
+        self.index().insert(&entry.key, entry.stale_until);
+        store.insert(entry.key.clone(), entry);
+        Ok(WriteOutcome::Stored { evicted: 0, expired: 0 })
+    }
+
+    async fn entries(&self) -> Result<Vec<SnapshotEntry<T>>, ServiceError> {
+        let now = Utc::now();
+        let store = self.store.read().await;
+        Ok(store
+            .values()
+            .filter(|entry| entry.stale_until > now)
+            .cloned()
+            .collect())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>, ServiceError> {
+        let now = Utc::now();
+        let store = self.store.read().await;
+        Ok(store
+            .values()
+            .filter(|entry| entry.stale_until > now)
+            .map(|entry| entry.key.clone())
+            .collect())
+    }
+
+    async fn len(&self) -> Result<usize, ServiceError> {
+        Ok(self.store.read().await.len())
+    }
+
+    async fn purge_expired(&self) -> Result<usize, ServiceError> {
+        let mut store = self.store.write().await;
+        Ok(self.purge(&mut store))
+    }
+}
@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+use crate::cache::SnapshotEntry;
+use crate::errors::ServiceError;
+use std::str::FromStr;
+
+pub mod memory;
+pub mod redis_store;
+
+pub use memory::MemoryBackend;
+pub use redis_store::{RedisBackend, RedisConnection};
+
+/// Where cache entries are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheBackendKind {
+    /// A map inside this process
+    #[default]
+    Memory,
+    /// A Redis server shared by every replica
+    Redis,
+}
+
+impl FromStr for CacheBackendKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "redis" => Ok(Self::Redis),
+            other => Err(format!("Unknown cache backend: {}", other)),
+        }
+    }
+}
+
+/// What happened to an entry handed to a backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+    /// Stored, after dropping this many evicted and expired entries to make room
+    Stored { evicted: u64, expired: u64 },
+    /// `put_new` found the key already present
+    Exists,
+    /// The backend has no room for the entry
+    Rejected,
+}
+
+/// Storage behind a `Cache`. Entries carry their own fresh and stale horizons;
+/// a backend must stop returning an entry once its `stale_until` has passed.
+#[async_trait]
+pub trait CacheBackend<T>: Send + Sync {
+    /// Short name used in logs and stats
+    fn name(&self) -> &'static str;
+
+    /// Maximum number of entries, for backends that enforce one
+    fn capacity(&self) -> Option<usize>;
+
+    /// The entry under `key`, counted as an access by backends that track them.
+    async fn get(&self, key: &str) -> Result<Option<SnapshotEntry<T>>, ServiceError>;
+
+    /// Like `get`, without counting as an access.
+    async fn peek(&self, key: &str) -> Result<Option<SnapshotEntry<T>>, ServiceError> {
+        self.get(key).await
+    }
+
+    /// Stores the entry, replacing any previous value under its key.
+    async fn put(&self, entry: SnapshotEntry<T>) -> Result<WriteOutcome, ServiceError>;
+
+    /// Stores the entry only if its key is absent and there is room without evicting.
+    async fn put_new(&self, entry: SnapshotEntry<T>) -> Result<WriteOutcome, ServiceError>;
+
+    /// Every entry that has not passed its stale horizon.
+    async fn entries(&self) -> Result<Vec<SnapshotEntry<T>>, ServiceError>;
+
+    /// Keys of those entries, without reading their values. Backends shared
+    /// with other replicas may answer from a recent listing.
+    async fn keys(&self) -> Result<Vec<String>, ServiceError> {
+        Ok(self.entries().await?.into_iter().map(|entry| entry.key).collect())
+    }
+
+    async fn len(&self) -> Result<usize, ServiceError>;
+
+    async fn is_empty(&self) -> Result<bool, ServiceError> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Drops entries past their stale horizon and returns how many went.
+    /// Backends with native key expiry have nothing to do here.
+    async fn purge_expired(&self) -> Result<usize, ServiceError>;
+}
@@ -0,0 +1,363 @@
+use async_trait::async_trait;
+use crate::cache::SnapshotEntry;
+use crate::cache_backend::{CacheBackend, WriteOutcome};
+use crate::errors::ServiceError;
+use chrono::Utc;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use serde::{de::DeserializeOwned, Serialize};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// Keys fetched per SCAN and MGET round-trip when listing entries.
+const SCAN_BATCH: usize = 100;
+
+/// Listing keys takes a full SCAN of the namespace, so a listing is reused for this long.
+const LISTING_REUSE: Duration = Duration::from_secs(30);
+
+fn redis_error(e: impl std::fmt::Display) -> ServiceError {
+    ServiceError::CacheError(format!("Redis request failed: {}", e))
+}
+
+/// One connection to a Redis server, shared by every cache stored there.
+///
+/// The connection is made on first use and re-established after failures,
+/// so an unreachable server only turns cache reads into misses.
+pub struct RedisConnection {
+    client: redis::Client,
+    manager: OnceCell<ConnectionManager>,
+}
+
+impl RedisConnection {
+    pub fn open(url: &str) -> Result<Self, ServiceError> {
+        let client = redis::Client::open(url)
+            .map_err(|e| ServiceError::ConfigError(format!("Invalid Redis URL {}: {}", url, e)))?;
+        Ok(Self {
+            client,
+            manager: OnceCell::new(),
+        })
+    }
+
+    async fn manager(&self) -> Result<ConnectionManager, ServiceError> {
+        self.manager
+            .get_or_try_init(|| {
+                // Fail fast: a slow cache is worse than going upstream
+                let config = ConnectionManagerConfig::new()
+                    .set_number_of_retries(0)
+                    .set_connection_timeout(Duration::from_secs(2))
+                    .set_response_timeout(Duration::from_secs(2));
+                ConnectionManager::new_with_config(self.client.clone(), config)
+            })
+            .await
+            .cloned()
+            .map_err(redis_error)
+    }
+}
+
+/// Entries stored in Redis as JSON under `<namespace>:<key>`. Each key expires
+/// natively at the end of the entry's stale window, and replicas pointed at the
+/// same server and namespace share the entries.
+///
+/// Size limits and eviction are left to the server's `maxmemory` settings.
+pub struct RedisBackend<T> {
+    connection: Arc<RedisConnection>,
+    prefix: String,
+    // Last key listing, without the namespace prefix, and when it was taken
+    listing: Mutex<Option<(Instant, Vec<String>)>>,
+    _values: PhantomData<fn() -> T>,
+}
+
+impl<T> RedisBackend<T> {
+    pub fn new(connection: Arc<RedisConnection>, namespace: &str) -> Self {
+        Self {
+            connection,
+            prefix: format!("{}:", namespace),
+            listing: Mutex::new(None),
+            _values: PhantomData,
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    async fn scan(&self, connection: &mut ConnectionManager) -> Result<Vec<String>, ServiceError> {
+        let pattern = format!("{}*", self.prefix);
+        let mut keys = Vec::new();
+        let mut cursor = 0u64;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_BATCH)
+                .query_async(connection)
+                .await
+                .map_err(redis_error)?;
+            keys.extend(batch);
+            if next == 0 {
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Send + Sync + 'static> RedisBackend<T> {
+    async fn write(&self, entry: SnapshotEntry<T>, only_new: bool) -> Result<WriteOutcome, ServiceError> {
+        let ttl_ms = (entry.stale_until - Utc::now()).num_milliseconds();
+        if ttl_ms <= 0 {
+            return Ok(WriteOutcome::Rejected);
+        }
+        let value = serde_json::to_string(&entry).map_err(redis_error)?;
+
+        let mut command = redis::cmd("SET");
+        command.arg(self.key(&entry.key)).arg(value).arg("PX").arg(ttl_ms);
+        if only_new {
+            command.arg("NX");
+        }
+        let mut connection = self.connection.manager().await?;
+        let reply: Option<String> = command.query_async(&mut connection).await.map_err(redis_error)?;
+        if reply.is_none() {
+            return Ok(WriteOutcome::Exists);
+        }
+        // Our own writes show up in the listing right away
+        if let Some((_, keys)) = self.listing.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).as_mut() {
+            if !keys.contains(&entry.key) {
+                keys.push(entry.key);
+            }
+        }
+        Ok(WriteOutcome::Stored { evicted: 0, expired: 0 })
+    }
+
+    fn decode(value: Option<String>) -> Result<Option<SnapshotEntry<T>>, ServiceError> {
+        value
+            .map(|value| serde_json::from_str(&value).map_err(redis_error))
+            .transpose()
+    }
+}
+
+#[async_trait]
+impl<T: Serialize + DeserializeOwned + Send + Sync + 'static> CacheBackend<T> for RedisBackend<T> {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<SnapshotEntry<T>>, ServiceError> {
+        let mut connection = self.connection.manager().await?;
+        let value: Option<String> = redis::cmd("GET")
+            .arg(self.key(key))
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error)?;
+        Self::decode(value)
+    }
+
+    async fn put(&self, entry: SnapshotEntry<T>) -> Result<WriteOutcome, ServiceError> {
+        self.write(entry, false).await
+    }
+
+    async fn put_new(&self, entry: SnapshotEntry<T>) -> Result<WriteOutcome, ServiceError> {
+        self.write(entry, true).await
+    }
+
+    async fn entries(&self) -> Result<Vec<SnapshotEntry<T>>, ServiceError> {
+        let mut connection = self.connection.manager().await?;
+        let keys = self.scan(&mut connection).await?;
+
+        let mut entries = Vec::with_capacity(keys.len());
+        for batch in keys.chunks(SCAN_BATCH) {
+            let values: Vec<Option<String>> = redis::cmd("MGET")
+                .arg(batch)
+                .query_async(&mut connection)
+                .await
+                .map_err(redis_error)?;
+            // Keys that expired between SCAN and MGET come back empty
+            for value in values {
+                entries.extend(Self::decode(value)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Approximate: a listing up to `LISTING_REUSE` old, as other replicas write too.
+    async fn keys(&self) -> Result<Vec<String>, ServiceError> {
+        {
+            let listing = self.listing.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some((_, keys)) = listing.as_ref().filter(|(taken, _)| taken.elapsed() < LISTING_REUSE) {
+                return Ok(keys.clone());
+            }
+        }
+
+        let mut connection = self.connection.manager().await?;
+        let keys: Vec<String> = self
+            .scan(&mut connection)
+            .await?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect();
+        *self.listing.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some((Instant::now(), keys.clone()));
+        Ok(keys)
+    }
+
+    /// Approximate, like `keys`.
+    async fn len(&self) -> Result<usize, ServiceError> {
+        Ok(self.keys().await?.len())
+    }
+
+    async fn purge_expired(&self) -> Result<usize, ServiceError> {
+        // Redis drops keys itself once their PX expiry passes
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::Cache;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::Instant;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    type FakeStore = Arc<Mutex<HashMap<String, (String, Option<Instant>)>>>;
+
+    /// Minimal in-process server speaking enough RESP for the backend.
+    async fn start_fake_redis() -> (String, FakeStore) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let store = FakeStore::default();
+        let server_store = Arc::clone(&store);
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(socket, Arc::clone(&server_store)));
+            }
+        });
+        (url, store)
+    }
+
+    async fn read_command(reader: &mut BufReader<TcpStream>) -> Option<Vec<String>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).await.ok()?;
+            let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).await.ok()?;
+            arg.truncate(len);
+            args.push(String::from_utf8(arg).ok()?);
+        }
+        Some(args)
+    }
+
+    fn bulk(value: Option<&str>) -> String {
+        match value {
+            Some(value) => format!("${}\r\n{}\r\n", value.len(), value),
+            None => "$-1\r\n".to_string(),
+        }
+    }
+
+    async fn serve(socket: TcpStream, store: FakeStore) {
+        let mut reader = BufReader::new(socket);
+        while let Some(args) = read_command(&mut reader).await {
+            let reply = {
+                let mut store = store.lock().unwrap();
+                store.retain(|_, (_, deadline)| deadline.is_none_or(|deadline| deadline > Instant::now()));
+                match args[0].to_ascii_uppercase().as_str() {
+                    "SET" => {
+                        let options: Vec<String> = args[3..].iter().map(|arg| arg.to_ascii_uppercase()).collect();
+                        let deadline = options
+                            .iter()
+                            .position(|option| option == "PX")
+                            .map(|at| Instant::now() + Duration::from_millis(args[4 + at].parse().unwrap()));
+                        if options.contains(&"NX".to_string()) && store.contains_key(&args[1]) {
+                            bulk(None)
+                        } else {
+                            store.insert(args[1].clone(), (args[2].clone(), deadline));
+                            "+OK\r\n".to_string()
+                        }
+                    }
+                    "GET" => bulk(store.get(&args[1]).map(|(value, _)| value.as_str())),
+                    "MGET" => {
+                        let values: Vec<String> = args[1..]
+                            .iter()
+                            .map(|key| bulk(store.get(key).map(|(value, _)| value.as_str())))
+                            .collect();
+                        format!("*{}\r\n{}", values.len(), values.concat())
+                    }
+                    "SCAN" => {
+                        let prefix = args[3].trim_end_matches('*');
+                        let keys: Vec<String> = store
+                            .keys()
+                            .filter(|key| key.starts_with(prefix))
+                            .map(|key| bulk(Some(key)))
+                            .collect();
+                        format!("*2\r\n{}*{}\r\n{}", bulk(Some("0")), keys.len(), keys.concat())
+                    }
+                    "CLIENT" => "+OK\r\n".to_string(),
+                    "PING" => "+PONG\r\n".to_string(),
+                    other => format!("-ERR unknown command '{}'\r\n", other),
+                }
+            };
+            if reader.get_mut().write_all(reply.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replicas_share_entries_through_redis() {
+        let (url, store) = start_fake_redis().await;
+        let connection = Arc::new(RedisConnection::open(&url).unwrap());
+        let replica = |namespace: &str| {
+            Cache::new(60, 10)
+                .with_stale_window(30)
+                .with_backend(Arc::new(RedisBackend::<f64>::new(Arc::clone(&connection), namespace)))
+                // Only the default in-memory backend is affected by the policy
+                .with_eviction_policy(crate::cache::EvictionPolicy::Lfu)
+        };
+        let first = replica("test:rates");
+        let second = replica("test:rates");
+
+        first.set("USD".to_string(), 1.5).await;
+        assert_eq!(second.get("USD").await, Some(1.5));
+        assert_eq!(second.entries().await, vec![("USD".to_string(), 1.5)]);
+        assert!(replica("test:other").get("USD").await.is_none());
+
+        // The key expires natively at the end of the stale window
+        let (_, deadline) = store.lock().unwrap()["test:rates:USD"].clone();
+        let expires_in = deadline.unwrap() - Instant::now();
+        assert!(expires_in > Duration::from_secs(89 * 60) && expires_in <= Duration::from_secs(90 * 60));
+
+        // Restoring never overwrites what another replica already stored
+        let restored = first.snapshot().await;
+        assert_eq!(second.restore(restored).await, 0);
+        let stats = second.stats().await;
+        assert_eq!((stats.backend.as_str(), stats.size), ("redis", 1));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_is_a_miss() {
+        let connection = Arc::new(RedisConnection::open("redis://127.0.0.1:1").unwrap());
+        let cache = Cache::new(60, 10).with_backend(Arc::new(RedisBackend::<f64>::new(connection, "test")));
+
+        cache.set("USD".to_string(), 1.5).await;
+        assert_eq!(cache.get("USD").await, None);
+        let stats = cache.stats().await;
+        assert_eq!((stats.inserts, stats.misses), (0, 1));
+        assert!(stats.backend_errors >= 2);
+        assert!(RedisConnection::open("not a url").is_err());
+    }
+}
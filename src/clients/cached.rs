@@ -0,0 +1,106 @@
+use async_trait::async_trait;
+use crate::cache::Cache;
+use crate::clients::{normalize_country_name, CountryClient};
+use crate::errors::ServiceError;
+use crate::models::CountryInfo;
+use log::debug;
+use std::sync::Arc;
+
+/// Serves country lookups from a cache keyed by normalized name, so repeated
+/// conversions skip the upstream round-trips.
+///
+/// Unknown names are remembered in a separate, shorter-lived cache so typos
+/// do not hit upstream on every request either. Other errors are not cached.
+pub struct CachingCountryClient {
+    inner: Arc<dyn CountryClient>,
+    cache: Arc<Cache<CountryInfo>>,
+    not_found: Arc<Cache<()>>,
+}
+
+impl CachingCountryClient {
+    pub fn new(
+        inner: Arc<dyn CountryClient>,
+        cache: Arc<Cache<CountryInfo>>,
+        not_found: Arc<Cache<()>>,
+    ) -> Self {
+        Self {
+            inner,
+            cache,
+            not_found,
+        }
+    }
+}
+
+#[async_trait]
+impl CountryClient for CachingCountryClient {
+    async fn get_country_info(&self, country_name: &str) -> Result<CountryInfo, ServiceError> {
+        let key = normalize_country_name(country_name);
+        if let Some(info) = self.cache.get(&key).await {
+            return Ok(info);
+        }
+        if self.not_found.get(&key).await.is_some() {
+            debug!("Country {} is cached as not found", country_name);
+            return Err(ServiceError::CountryNotFound(country_name.to_string()));
+        }
+
+        match self.inner.get_country_info(country_name).await {
+            Ok(info) => {
+                self.cache.set(key, info.clone()).await;
+                Ok(info)
+            }
+            Err(ServiceError::CountryNotFound(name)) => {
+                self.not_found.set(key, ()).await;
+                Err(ServiceError::CountryNotFound(name))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::tests::{create_test_country_info, MockClient};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingClient {
+        inner: MockClient,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl CountryClient for CountingClient {
+        async fn get_country_info(&self, country_name: &str) -> Result<CountryInfo, ServiceError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.inner.get_country_info(country_name).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_caches_found_and_not_found_countries() {
+        let upstream = Arc::new(CountingClient {
+            inner: MockClient::new()
+                .with_country_response(create_test_country_info("France", "EUR", "Euro", "€")),
+            calls: AtomicUsize::new(0),
+        });
+        let client = CachingCountryClient::new(
+            Arc::clone(&upstream) as Arc<dyn CountryClient>,
+            Arc::new(Cache::new(60, 10)),
+            Arc::new(Cache::new(5, 10)),
+        );
+
+        assert!(client.get_country_info("France").await.is_ok());
+        // Differently spelled lookups share the cached entry
+        let info = client.get_country_info("  FRANCE ").await.unwrap();
+        assert!(info.currencies.contains_key("EUR"));
+        assert_eq!(upstream.calls.load(Ordering::SeqCst), 1);
+
+        for _ in 0..2 {
+            assert!(matches!(
+                client.get_country_info("Narnia").await,
+                Err(ServiceError::CountryNotFound(_))
+            ));
+        }
+        assert_eq!(upstream.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,315 @@
+use async_trait::async_trait;
+use crate::clients::{CountryClient, ExchangeRateClient};
+use crate::config::CircuitBreakerSettings;
+use crate::errors::ServiceError;
+use crate::models::{CountryInfo, ExchangeRateResponse};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitBreakerStatus {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opened_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<(Instant, DateTime<Utc>)>,
+    // Probe started in half-open state; an abandoned probe expires after the cooldown
+    trial_started: Option<Instant>,
+}
+
+/// Stops calling an upstream after repeated failures and probes it again after a cooldown.
+pub struct CircuitBreaker {
+    name: String,
+    failure_threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: impl Into<String>, failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            name: name.into(),
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                trial_started: None,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BreakerState> {
+        // State stays consistent even if a holder panicked, so recover from poisoning
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn status(&self) -> CircuitBreakerStatus {
+        let inner = self.lock();
+        CircuitBreakerStatus {
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+            opened_at: inner.opened_at.map(|(_, at)| at),
+        }
+    }
+
+    /// Only upstream outages trip the breaker; answers such as "not found" mean the upstream is healthy.
+    fn is_failure(error: &ServiceError) -> bool {
+        matches!(error, ServiceError::ExternalApiError(_) | ServiceError::ServiceUnavailable(_))
+    }
+
+    fn try_acquire(&self) -> Result<(), ServiceError> {
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open => {
+                let cooled_down = inner
+                    .opened_at
+                    .map(|(at, _)| at.elapsed() >= self.cooldown)
+                    .unwrap_or(true);
+                if cooled_down {
+                    info!("Circuit for {} is half-open, probing upstream", self.name);
+                    inner.state = CircuitState::HalfOpen;
+                    inner.trial_started = Some(Instant::now());
+                    Ok(())
+                } else {
+                    Err(self.open_error())
+                }
+            }
+            CircuitState::HalfOpen => {
+                let probe_pending = inner
+                    .trial_started
+                    .map(|started| started.elapsed() < self.cooldown)
+                    .unwrap_or(false);
+                if probe_pending {
+                    return Err(self.open_error());
+                }
+                inner.trial_started = Some(Instant::now());
+                Ok(())
+            }
+        }
+    }
+
+    fn record(&self, success: bool) {
+        let mut inner = self.lock();
+        inner.trial_started = None;
+        if success {
+            if inner.state != CircuitState::Closed {
+                info!("Circuit for {} closed", self.name);
+            }
+            inner.state = CircuitState::Closed;
+            inner.consecutive_failures = 0;
+            inner.opened_at = None;
+            return;
+        }
+
+        inner.consecutive_failures += 1;
+        if inner.state == CircuitState::HalfOpen || inner.consecutive_failures >= self.failure_threshold {
+            if inner.state != CircuitState::Open {
+                warn!(
+                    "Circuit for {} opened after {} consecutive failures",
+                    self.name, inner.consecutive_failures
+                );
+            }
+            inner.state = CircuitState::Open;
+            inner.opened_at = Some((Instant::now(), Utc::now()));
+        }
+    }
+
+    fn open_error(&self) -> ServiceError {
+        ServiceError::ServiceUnavailable(format!("Circuit open for {}", self.name))
+    }
+
+    /// Runs `call` through the breaker, failing fast while the circuit is open.
+    pub async fn call<T, F, Fut>(&self, call: F) -> Result<T, ServiceError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, ServiceError>>,
+    {
+        self.try_acquire()?;
+        let result = call().await;
+        match &result {
+            Err(e) if Self::is_failure(e) => self.record(false),
+            // Throttling, by the vendor or our own quota, says nothing about health:
+            // free the probe slot, change nothing else
+            Err(ServiceError::RateLimitExceeded | ServiceError::QuotaExhausted(_)) => self.lock().trial_started = None,
+            _ => self.record(true),
+        }
+        result
+    }
+}
+
+/// Named breakers shared by every client talking to the same upstream.
+pub struct CircuitBreakers {
+    settings: CircuitBreakerSettings,
+    breakers: Mutex<BTreeMap<String, Arc<CircuitBreaker>>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(settings: CircuitBreakerSettings) -> Self {
+        Self {
+            settings,
+            breakers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, Arc<CircuitBreaker>>> {
+        self.breakers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn get(&self, name: &str) -> Arc<CircuitBreaker> {
+        let mut breakers = self.lock();
+        Arc::clone(breakers.entry(name.to_string()).or_insert_with(|| {
+            Arc::new(CircuitBreaker::new(
+                name,
+                self.settings.failure_threshold,
+                Duration::from_secs(self.settings.cooldown_seconds),
+            ))
+        }))
+    }
+
+    pub fn statuses(&self) -> BTreeMap<String, CircuitBreakerStatus> {
+        self.lock()
+            .iter()
+            .map(|(name, breaker)| (name.clone(), breaker.status()))
+            .collect()
+    }
+}
+
+/// Guards a client with a circuit breaker.
+pub struct CircuitBreakerClient<C: ?Sized> {
+    inner: Arc<C>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<C: ?Sized> CircuitBreakerClient<C> {
+    pub fn new(inner: Arc<C>, breaker: Arc<CircuitBreaker>) -> Self {
+        Self { inner, breaker }
+    }
+}
+
+#[async_trait]
+impl<C: CountryClient + ?Sized> CountryClient for CircuitBreakerClient<C> {
+    async fn get_country_info(&self, country_name: &str) -> Result<CountryInfo, ServiceError> {
+        self.breaker.call(|| self.inner.get_country_info(country_name)).await
+    }
+}
+
+#[async_trait]
+impl<C: ExchangeRateClient + ?Sized> ExchangeRateClient for CircuitBreakerClient<C> {
+    async fn get_exchange_rate(
+        &self,
+        from_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        self.breaker.call(|| self.inner.get_exchange_rate(from_currency)).await
+    }
+
+    async fn get_exchange_rate_pair(
+        &self,
+        from_currency: &str,
+        to_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        self.breaker
+            .call(|| self.inner.get_exchange_rate_pair(from_currency, to_currency))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn fail(breaker: &CircuitBreaker) -> Result<(), ServiceError> {
+        breaker
+            .call(|| async { Err::<(), _>(ServiceError::ExternalApiError("down".to_string())) })
+            .await
+    }
+
+    async fn succeed(breaker: &CircuitBreaker) -> Result<(), ServiceError> {
+        breaker.call(|| async { Ok(()) }).await
+    }
+
+    #[tokio::test]
+    async fn test_breaker_opens_and_recovers() {
+        let breaker = CircuitBreaker::new("test", 2, Duration::from_millis(20));
+
+        assert!(fail(&breaker).await.is_err());
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert!(fail(&breaker).await.is_err());
+        assert_eq!(breaker.status().state, CircuitState::Open);
+
+        // Fails fast without invoking the call while open
+        let result = breaker.call(|| async { panic!("call must not run") }).await;
+        assert!(matches!(result, Err::<(), _>(ServiceError::ServiceUnavailable(_))));
+
+        // A failed probe after the cooldown reopens the circuit
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        assert!(fail(&breaker).await.is_err());
+        assert_eq!(breaker.status().state, CircuitState::Open);
+
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        assert!(succeed(&breaker).await.is_ok());
+        let status = breaker.status();
+        assert_eq!(status.state, CircuitState::Closed);
+        assert_eq!(status.consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_not_found_does_not_trip_breaker() {
+        let breaker = CircuitBreaker::new("test", 1, Duration::from_secs(60));
+        let result = breaker
+            .call(|| async { Err::<(), _>(ServiceError::CountryNotFound("Narnia".to_string())) })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_probe_leaves_breaker_half_open() {
+        let breaker = CircuitBreaker::new("test", 1, Duration::from_millis(20));
+        assert!(fail(&breaker).await.is_err());
+        tokio::time::sleep(Duration::from_millis(25)).await;
+
+        let result = breaker.call(|| async { Err::<(), _>(ServiceError::RateLimitExceeded) }).await;
+        assert!(matches!(result, Err(ServiceError::RateLimitExceeded)));
+        let status = breaker.status();
+        assert_eq!((status.state, status.consecutive_failures), (CircuitState::HalfOpen, 1));
+
+        // The next call probes again right away
+        assert!(succeed(&breaker).await.is_ok());
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_breakers_are_shared_by_name() {
+        let breakers = CircuitBreakers::new(CircuitBreakerSettings::default());
+        let first = breakers.get("restcountries");
+        let second = breakers.get("restcountries");
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(breakers.statuses().len(), 1);
+    }
+}
@@ -2,8 +2,42 @@ use async_trait::async_trait;
 use crate::errors::ServiceError;
 use crate::models::{CountryInfo, ExchangeRateResponse};
 use log::{debug, error};
+use std::sync::Arc;
 use std::time::Duration;
 
+pub mod cached;
+pub mod circuit_breaker;
+pub mod offline;
+pub mod providers;
+pub mod quota;
+pub mod retry;
+pub mod timed;
+
+pub use cached::CachingCountryClient;
+pub use circuit_breaker::{CircuitBreakerClient, CircuitBreakers};
+pub use offline::{FallbackCountryClient, OfflineCountryClient};
+pub use providers::{FailoverClient, ProviderRegistry};
+pub use quota::{QuotaGovernedClient, QuotaMode, UpstreamQuota};
+pub use retry::{RetryErrorKind, RetryPolicy};
+pub use timed::TimedClient;
+
+pub const REST_COUNTRIES_BASE_URL: &str = "https://restcountries.com/v3.1";
+pub const EXCHANGE_RATE_API_BASE_URL: &str = "https://v6.exchangerate-api.com/v6";
+
+/// Circuit breaker name for restcountries.com.
+pub const REST_COUNTRIES_UPSTREAM: &str = "restcountries";
+
+/// Source reported for rates served by exchangerate-api.com.
+pub const EXCHANGE_RATE_API_SOURCE: &str = "exchangerate-api.com";
+
+/// Lowercases and collapses whitespace so country lookups ignore case and spacing.
+pub fn normalize_country_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
 #[async_trait]
 pub trait CountryClient: Send + Sync {
     async fn get_country_info(&self, country_name: &str) -> Result<CountryInfo, ServiceError>;
@@ -15,41 +49,90 @@ pub trait ExchangeRateClient: Send + Sync {
         &self,
         from_currency: &str,
     ) -> Result<ExchangeRateResponse, ServiceError>;
+
+    /// Fetches rates for `from_currency`, failing when `to_currency` is not quoted.
+    async fn get_exchange_rate_pair(
+        &self,
+        from_currency: &str,
+        to_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        let response = self.get_exchange_rate(from_currency).await?;
+        if !response.conversion_rates.contains_key(to_currency) {
+            return Err(ServiceError::InvalidCurrency(format!(
+                "Exchange rate not found for {}->{}", from_currency, to_currency
+            )));
+        }
+        Ok(response)
+    }
 }
 
 pub struct HttpClient {
     client: reqwest::Client,
     api_key: String,
+    retry_policy: RetryPolicy,
+    /// Plan that rate fetch retries are charged to; the first attempt is
+    /// charged by `QuotaGovernedClient`
+    quota: Option<Arc<UpstreamQuota>>,
+    countries_base_url: String,
+    rates_base_url: String,
 }
 
 impl HttpClient {
     pub fn new(client: reqwest::Client, api_key: String) -> Self {
-        Self { client, api_key }
+        Self {
+            client,
+            api_key,
+            retry_policy: RetryPolicy::default(),
+            quota: None,
+            countries_base_url: REST_COUNTRIES_BASE_URL.to_string(),
+            rates_base_url: EXCHANGE_RATE_API_BASE_URL.to_string(),
+        }
+    }
+
+    /// Points the client at different restcountries and exchangerate-api hosts.
+    pub fn with_base_urls(mut self, countries_base_url: &str, rates_base_url: &str) -> Self {
+        self.countries_base_url = countries_base_url.trim_end_matches('/').to_string();
+        self.rates_base_url = rates_base_url.trim_end_matches('/').to_string();
+        self
     }
 
     pub fn with_timeouts(timeout: Duration, api_key: String) -> Result<Self, ServiceError> {
-        let client = reqwest::Client::builder()
-            .timeout(timeout)
-            .connect_timeout(timeout)
-            .build()
-            .map_err(|e| ServiceError::ConfigError(format!("Failed to create HTTP client: {}", e)))?;
+        Ok(Self::new(build_http_client(timeout)?, api_key))
+    }
 
-        Ok(Self { client, api_key })
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
     }
+
+    /// Charges every retried rate fetch to `quota`, and stops retrying once it is spent.
+    pub fn with_quota(mut self, quota: Option<Arc<UpstreamQuota>>) -> Self {
+        self.quota = quota;
+        self
+    }
+}
+
+/// Builds a reqwest client with the given request and connect timeout.
+pub fn build_http_client(timeout: Duration) -> Result<reqwest::Client, ServiceError> {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .connect_timeout(timeout)
+        .build()
+        .map_err(|e| ServiceError::ConfigError(format!("Failed to create HTTP client: {}", e)))
 }
 
 #[async_trait]
 impl CountryClient for HttpClient {
     async fn get_country_info(&self, country_name: &str) -> Result<CountryInfo, ServiceError> {
         let url = format!(
-            "https://restcountries.com/v3.1/name/{}?fields=name,currencies",
+            "{}/name/{}?fields=name,currencies",
+            self.countries_base_url,
             urlencoding::encode(country_name)
         );
         
         debug!("Fetching country info for: {}", country_name);
-        let response = self.client
-            .get(&url)
-            .send()
+        let response = self.retry_policy
+            .send(self.client.get(&url))
             .await?;
 
         if response.status() == reqwest::StatusCode::NOT_FOUND {
@@ -87,15 +170,20 @@ impl ExchangeRateClient for HttpClient {
         from_currency: &str,
     ) -> Result<ExchangeRateResponse, ServiceError> {
         let url = format!(
-            "https://v6.exchangerate-api.com/v6/{}/latest/{}",
-            self.api_key, from_currency
+            "{}/{}/latest/{}",
+            self.rates_base_url, self.api_key, from_currency
         );
         
         debug!("Fetching exchange rates for: {}", from_currency);
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+        let request = self.client.get(&url);
+        let response = match &self.quota {
+            Some(quota) => {
+                self.retry_policy
+                    .send_with(request, || async { quota.acquire(false).await.is_ok() })
+                    .await?
+            }
+            None => self.retry_policy.send(request).await?,
+        };
 
         if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
             error!("Exchange rate API rate limit exceeded");
@@ -109,13 +197,16 @@ impl ExchangeRateClient for HttpClient {
             ));
         }
 
-        response
+        let mut rates: ExchangeRateResponse = response
             .json()
             .await
             .map_err(|e| {
                 error!("Failed to parse exchange rate data: {}", e);
                 ServiceError::ExternalApiError(format!("Failed to parse exchange rate data: {}", e))
-            })
+            })?;
+
+        rates.source = EXCHANGE_RATE_API_SOURCE.to_string();
+        Ok(rates)
     }
 }
 
@@ -130,6 +221,12 @@ pub mod tests {
         pub rate_response: Option<ExchangeRateResponse>,
     }
 
+    impl Default for MockClient {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
     impl MockClient {
         pub fn new() -> Self {
             Self {
@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use crate::clients::{normalize_country_name as normalize, CountryClient};
+use crate::errors::ServiceError;
+use crate::models::{CountryInfo, CountryName, CurrencyInfo};
+use log::{debug, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Source name used when selecting the bundled dataset in configuration.
+pub const OFFLINE_COUNTRY_SOURCE: &str = "offline";
+
+/// ISO 3166 countries, name variants and currencies bundled into the binary.
+const COUNTRIES_JSON: &str = include_str!("../../data/countries.json");
+
+#[derive(Debug, Deserialize)]
+struct CountryRecord {
+    cca2: String,
+    cca3: String,
+    name: CountryName,
+    #[serde(default)]
+    alt_names: Vec<String>,
+    currencies: HashMap<String, CurrencyInfo>,
+}
+
+/// Resolves countries from the bundled dataset without any network access.
+///
+/// Lookups match the common and official names, known variants such as
+/// "USA" or "Holland", and the ISO alpha-2 and alpha-3 codes.
+pub struct OfflineCountryClient {
+    countries: Vec<CountryInfo>,
+    index: HashMap<String, usize>,
+}
+
+impl Default for OfflineCountryClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OfflineCountryClient {
+    pub fn new() -> Self {
+        Self::from_json(COUNTRIES_JSON).expect("bundled country dataset is valid")
+    }
+
+    /// Builds the client from a dataset in the bundled JSON layout.
+    pub fn from_json(json: &str) -> Result<Self, ServiceError> {
+        let records: Vec<CountryRecord> = serde_json::from_str(json)
+            .map_err(|e| ServiceError::ConfigError(format!("Invalid country dataset: {}", e)))?;
+
+        let mut countries = Vec::with_capacity(records.len());
+        let mut index = HashMap::new();
+        for record in records {
+            let position = countries.len();
+            let names = [&record.name.common, &record.name.official, &record.cca2, &record.cca3]
+                .into_iter()
+                .chain(record.alt_names.iter());
+            for name in names {
+                // The first country to claim a name keeps it
+                index.entry(normalize(name)).or_insert(position);
+            }
+            countries.push(CountryInfo {
+                name: record.name,
+                currencies: record.currencies,
+            });
+        }
+
+        Ok(Self { countries, index })
+    }
+
+    pub fn len(&self) -> usize {
+        self.countries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.countries.is_empty()
+    }
+}
+
+#[async_trait]
+impl CountryClient for OfflineCountryClient {
+    async fn get_country_info(&self, country_name: &str) -> Result<CountryInfo, ServiceError> {
+        debug!("Looking up country in bundled dataset: {}", country_name);
+        self.index
+            .get(&normalize(country_name))
+            .map(|&position| self.countries[position].clone())
+            .ok_or_else(|| ServiceError::CountryNotFound(country_name.to_string()))
+    }
+}
+
+/// Answers from `primary` and falls back to `fallback` whenever it fails,
+/// e.g. restcountries.com backed by the bundled dataset.
+pub struct FallbackCountryClient {
+    primary: Arc<dyn CountryClient>,
+    fallback: Arc<dyn CountryClient>,
+}
+
+impl FallbackCountryClient {
+    pub fn new(primary: Arc<dyn CountryClient>, fallback: Arc<dyn CountryClient>) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait]
+impl CountryClient for FallbackCountryClient {
+    async fn get_country_info(&self, country_name: &str) -> Result<CountryInfo, ServiceError> {
+        match self.primary.get_country_info(country_name).await {
+            Ok(info) => Ok(info),
+            // The fallback may still know a variant the primary does not
+            Err(ServiceError::CountryNotFound(_)) => self.fallback.get_country_info(country_name).await,
+            Err(e) => {
+                warn!("Country lookup for {} failed, using fallback: {}", country_name, e);
+                self.fallback.get_country_info(country_name).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::tests::MockClient;
+
+    struct UnreachableClient;
+
+    #[async_trait]
+    impl CountryClient for UnreachableClient {
+        async fn get_country_info(&self, _country_name: &str) -> Result<CountryInfo, ServiceError> {
+            Err(ServiceError::ExternalApiError("connection refused".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bundled_dataset_lookup() {
+        let client = OfflineCountryClient::new();
+        assert!(client.len() >= 249);
+
+        for name in ["United States", "united  states", "USA", "United States of America", "US"] {
+            let info = client.get_country_info(name).await.unwrap();
+            assert_eq!(info.name.common, "United States");
+            assert!(info.currencies.contains_key("USD"));
+        }
+
+        let panama = client.get_country_info("Panama").await.unwrap();
+        assert!(panama.currencies.contains_key("PAB") && panama.currencies.contains_key("USD"));
+        assert_eq!(client.get_country_info("Cote d'Ivoire").await.unwrap().name.common, "Ivory Coast");
+
+        assert!(matches!(
+            client.get_country_info("Narnia").await,
+            Err(ServiceError::CountryNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_used_when_primary_fails() {
+        let client = FallbackCountryClient::new(
+            Arc::new(UnreachableClient),
+            Arc::new(OfflineCountryClient::new()),
+        );
+        let info = client.get_country_info("Japan").await.unwrap();
+        assert!(info.currencies.contains_key("JPY"));
+
+        let client = FallbackCountryClient::new(
+            Arc::new(MockClient::new()),
+            Arc::new(OfflineCountryClient::new()),
+        );
+        assert_eq!(client.get_country_info("Holland").await.unwrap().name.common, "Netherlands");
+        assert!(matches!(
+            client.get_country_info("Narnia").await,
+            Err(ServiceError::CountryNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use async_trait::async_trait;
+use crate::clients::{ExchangeRateClient, RetryPolicy};
+use crate::errors::ServiceError;
+use crate::models::ExchangeRateResponse;
+use log::{debug, error};
+use std::collections::HashMap;
+
+pub const ECB_DAILY_FEED_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml";
+pub const ECB_SOURCE: &str = "ecb.europa.eu";
+
+/// Euro reference rates from the European Central Bank daily XML feed.
+pub struct EcbClient {
+    client: reqwest::Client,
+    feed_url: String,
+    retry_policy: RetryPolicy,
+}
+
+impl EcbClient {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self::with_url(client, ECB_DAILY_FEED_URL)
+    }
+
+    pub fn with_url(client: reqwest::Client, feed_url: impl Into<String>) -> Self {
+        Self {
+            client,
+            feed_url: feed_url.into(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+}
+
+/// Extracts the value of a `name='...'` or `name="..."` attribute from a tag.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    for quote in ['\'', '"'] {
+        let needle = format!("{}={}", name, quote);
+        if let Some(start) = tag.find(&needle) {
+            let rest = &tag[start + needle.len()..];
+            return rest.find(quote).map(|end| &rest[..end]);
+        }
+    }
+    None
+}
+
+/// Parses the ECB feed into EUR-based rates and the publication date.
+pub fn parse_feed(xml: &str) -> Result<(HashMap<String, f64>, Option<String>), ServiceError> {
+    let mut rates = HashMap::new();
+    let mut time = None;
+
+    for tag in xml.split('<').filter(|t| t.starts_with("Cube")) {
+        if let Some(date) = attribute(tag, "time") {
+            time = Some(date.to_string());
+        }
+        if let (Some(currency), Some(rate)) = (attribute(tag, "currency"), attribute(tag, "rate")) {
+            let rate = rate.parse::<f64>().map_err(|e| {
+                ServiceError::ExternalApiError(format!("Invalid ECB rate for {}: {}", currency, e))
+            })?;
+            rates.insert(currency.to_string(), rate);
+        }
+    }
+
+    if rates.is_empty() {
+        return Err(ServiceError::ExternalApiError("ECB feed contained no rates".to_string()));
+    }
+    Ok((rates, time))
+}
+
+#[async_trait]
+impl ExchangeRateClient for EcbClient {
+    async fn get_exchange_rate(
+        &self,
+        from_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        debug!("Fetching ECB reference rates for: {}", from_currency);
+        let response = self.retry_policy
+            .send(self.client.get(&self.feed_url))
+            .await?;
+
+        if !response.status().is_success() {
+            error!("ECB feed error: {}", response.status());
+            return Err(ServiceError::ServiceUnavailable(
+                "ECB reference rate feed unavailable".to_string()
+            ));
+        }
+
+        let body = response.text().await?;
+        let (rates, time) = parse_feed(&body)?;
+        super::into_response("EUR", rates, from_currency, time, ECB_SOURCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+    <gesmes:subject>Reference rates</gesmes:subject>
+    <Cube>
+        <Cube time='2024-01-02'>
+            <Cube currency='USD' rate='1.0956'/>
+            <Cube currency='JPY' rate='155.52'/>
+        </Cube>
+    </Cube>
+</gesmes:Envelope>"#;
+
+    #[test]
+    fn test_parse_feed() {
+        let (rates, time) = parse_feed(FEED).unwrap();
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates["USD"], 1.0956);
+        assert_eq!(rates["JPY"], 155.52);
+        assert_eq!(time.as_deref(), Some("2024-01-02"));
+
+        assert!(parse_feed("<Cube></Cube>").is_err());
+    }
+}
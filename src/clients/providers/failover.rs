@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+use crate::clients::ExchangeRateClient;
+use crate::errors::ServiceError;
+use crate::models::ExchangeRateResponse;
+use log::{info, warn};
+use std::sync::Arc;
+
+/// Tries an ordered list of providers until one of them can serve the request.
+pub struct FailoverClient {
+    providers: Vec<(String, Arc<dyn ExchangeRateClient>)>,
+}
+
+impl FailoverClient {
+    pub fn new(providers: Vec<(String, Arc<dyn ExchangeRateClient>)>) -> Self {
+        Self { providers }
+    }
+
+    pub fn provider_names(&self) -> Vec<&str> {
+        self.providers.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    async fn try_providers(
+        &self,
+        from_currency: &str,
+        to_currency: Option<&str>,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        let mut last_error = ServiceError::ConfigError("No exchange rate providers configured".to_string());
+
+        for (position, (name, provider)) in self.providers.iter().enumerate() {
+            let result = match to_currency {
+                Some(to_currency) => provider.get_exchange_rate_pair(from_currency, to_currency).await,
+                None => provider.get_exchange_rate(from_currency).await,
+            };
+
+            match result {
+                Ok(mut response) => {
+                    info!(
+                        "Exchange rates for {} served by provider {} (attempt {}/{})",
+                        from_currency, name, position + 1, self.providers.len()
+                    );
+                    if response.source.is_empty() {
+                        response.source = name.clone();
+                    }
+                    return Ok(response);
+                }
+                Err(e) => {
+                    warn!(
+                        "Provider {} failed for {} (attempt {}/{}): {}",
+                        name, from_currency, position + 1, self.providers.len(), e
+                    );
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+#[async_trait]
+impl ExchangeRateClient for FailoverClient {
+    async fn get_exchange_rate(
+        &self,
+        from_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        self.try_providers(from_currency, None).await
+    }
+
+    async fn get_exchange_rate_pair(
+        &self,
+        from_currency: &str,
+        to_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        self.try_providers(from_currency, Some(to_currency)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::tests::MockClient;
+    use std::collections::HashMap;
+
+    fn rates(source: &str, codes: &[&str]) -> ExchangeRateResponse {
+        ExchangeRateResponse {
+            result: "success".to_string(),
+            conversion_rates: codes.iter().map(|c| (c.to_string(), 1.0)).collect::<HashMap<_, _>>(),
+            time_last_update_utc: None,
+            source: source.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_next_provider() {
+        let client = FailoverClient::new(vec![
+            ("down".to_string(), Arc::new(MockClient::new()) as Arc<dyn ExchangeRateClient>),
+            ("partial".to_string(), Arc::new(MockClient::new().with_rate_response(rates("partial", &["USD"])))),
+            ("full".to_string(), Arc::new(MockClient::new().with_rate_response(rates("full", &["USD", "EUR"])))),
+        ]);
+
+        // The partial provider answers unless a currency it lacks is requested
+        let response = client.get_exchange_rate("USD").await.unwrap();
+        assert_eq!(response.source, "partial");
+
+        let response = client.get_exchange_rate_pair("USD", "EUR").await.unwrap();
+        assert_eq!(response.source, "full");
+    }
+
+    #[tokio::test]
+    async fn test_failover_returns_last_error() {
+        let client = FailoverClient::new(vec![
+            ("first".to_string(), Arc::new(MockClient::new()) as Arc<dyn ExchangeRateClient>),
+            ("second".to_string(), Arc::new(MockClient::new().with_rate_response(rates("second", &["USD"])))),
+        ]);
+
+        let result = client.get_exchange_rate_pair("USD", "EUR").await;
+        assert!(matches!(result, Err(ServiceError::InvalidCurrency(_))));
+    }
+}
@@ -0,0 +1,304 @@
+use crate::cache::{Cache, ExchangeRateData};
+use crate::clients::{
+    build_http_client, CircuitBreakerClient, CircuitBreakers, ExchangeRateClient, HttpClient, QuotaGovernedClient,
+    RetryPolicy, TimedClient, UpstreamQuota,
+};
+use crate::config::Config;
+use crate::errors::ServiceError;
+use crate::models::ExchangeRateResponse;
+use crate::monitor::{UsageMonitor, RATES_DEPENDENCY};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod ecb;
+pub mod failover;
+pub mod open_exchange_rates;
+pub mod static_file;
+
+pub use ecb::EcbClient;
+pub use failover::FailoverClient;
+pub use open_exchange_rates::OpenExchangeRatesClient;
+pub use static_file::StaticFileClient;
+
+/// The only provider that bills per call, and so the only one the upstream quota governs.
+pub const EXCHANGE_RATE_API_PROVIDER: &str = "exchangerate-api";
+
+/// Builds an exchange rate provider from the service configuration. Metered
+/// providers charge every HTTP attempt past the first to the quota, when given.
+pub type ProviderFactory =
+    fn(&Config, Option<&Arc<UpstreamQuota>>) -> Result<Arc<dyn ExchangeRateClient>, ServiceError>;
+
+/// Maps provider names, as used in configuration, to the factories that build them.
+pub struct ProviderRegistry {
+    factories: HashMap<String, ProviderFactory>,
+    /// Plan governing the metered provider, and the cache it checks for tables to fall back on
+    quota: Option<(Arc<UpstreamQuota>, Arc<Cache<ExchangeRateData>>)>,
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::with_defaults()
+    }
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self {
+            factories: HashMap::new(),
+            quota: None,
+        }
+    }
+
+    /// Governs the metered provider by `quota`; the other providers stay free to call.
+    pub fn with_quota(mut self, quota: Arc<UpstreamQuota>, cache: Arc<Cache<ExchangeRateData>>) -> Self {
+        self.quota = Some((quota, cache));
+        self
+    }
+
+    /// Registry pre-populated with every provider shipped with the service.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry.register(EXCHANGE_RATE_API_PROVIDER, build_exchange_rate_api);
+        registry.register("ecb", build_ecb);
+        registry.register("open-exchange-rates", build_open_exchange_rates);
+        registry.register("static-file", build_static_file);
+        registry
+    }
+
+    pub fn register(&mut self, name: impl Into<String>, factory: ProviderFactory) {
+        self.factories.insert(name.into(), factory);
+    }
+
+    pub fn build(&self, name: &str, config: &Config) -> Result<Arc<dyn ExchangeRateClient>, ServiceError> {
+        let factory = self.factories.get(name).ok_or_else(|| {
+            ServiceError::ConfigError(format!("Unknown exchange rate provider: {}", name))
+        })?;
+        factory(config, self.quota.as_ref().map(|(quota, _)| quota))
+    }
+
+    /// Builds the named providers, in order, behind a single failover client.
+    /// Each provider is timed and guarded by the circuit breaker registered
+    /// under its name. The metered one is governed by the quota inside its
+    /// breaker, so calls the open breaker refuses are never charged, and
+    /// quota refusals leave the breaker alone.
+    pub fn build_chain(
+        &self,
+        names: &[String],
+        config: &Config,
+        monitor: Arc<UsageMonitor>,
+        breakers: &CircuitBreakers,
+    ) -> Result<FailoverClient, ServiceError> {
+        if names.is_empty() {
+            return Err(ServiceError::ConfigError("No exchange rate providers configured".to_string()));
+        }
+
+        let providers = names
+            .iter()
+            .map(|name| {
+                let timed = TimedClient::new(self.build(name, config)?, RATES_DEPENDENCY, Arc::clone(&monitor))
+                    .with_provider(name);
+                let provider: Arc<dyn ExchangeRateClient> = Arc::new(timed);
+                let provider: Arc<dyn ExchangeRateClient> = match &self.quota {
+                    Some((quota, cache)) if name == EXCHANGE_RATE_API_PROVIDER => {
+                        Arc::new(QuotaGovernedClient::new(provider, Arc::clone(quota), Arc::clone(cache)))
+                    }
+                    _ => provider,
+                };
+                let provider: Arc<dyn ExchangeRateClient> =
+                    Arc::new(CircuitBreakerClient::new(provider, breakers.get(name)));
+                Ok((name.clone(), provider))
+            })
+            .collect::<Result<Vec<_>, ServiceError>>()?;
+
+        Ok(FailoverClient::new(providers))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.factories.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+fn build_exchange_rate_api(
+    config: &Config,
+    quota: Option<&Arc<UpstreamQuota>>,
+) -> Result<Arc<dyn ExchangeRateClient>, ServiceError> {
+    if config.exchange_rate_api_key.is_empty() {
+        return Err(ServiceError::ConfigError("EXCHANGE_RATE_API_KEY not set".to_string()));
+    }
+    let upstream = &config.upstream_settings;
+    Ok(Arc::new(
+        HttpClient::with_timeouts(Duration::from_secs(upstream.timeout_seconds), config.exchange_rate_api_key.clone())?
+            .with_base_urls(&upstream.rest_countries_base_url, &upstream.exchange_rate_api_base_url)
+            .with_retry_policy(RetryPolicy::from_settings(&config.retry_settings))
+            .with_quota(quota.cloned()),
+    ))
+}
+
+fn build_ecb(config: &Config, _quota: Option<&Arc<UpstreamQuota>>) -> Result<Arc<dyn ExchangeRateClient>, ServiceError> {
+    Ok(Arc::new(
+        EcbClient::with_url(
+            build_http_client(Duration::from_secs(config.upstream_settings.timeout_seconds))?,
+            &config.upstream_settings.ecb_feed_url,
+        )
+        .with_retry_policy(RetryPolicy::from_settings(&config.retry_settings)),
+    ))
+}
+
+fn build_open_exchange_rates(config: &Config, _quota: Option<&Arc<UpstreamQuota>>) -> Result<Arc<dyn ExchangeRateClient>, ServiceError> {
+    let app_id = config.provider_settings.open_exchange_rates_app_id.clone().ok_or_else(|| {
+        ServiceError::ConfigError("OPEN_EXCHANGE_RATES_APP_ID not set".to_string())
+    })?;
+    Ok(Arc::new(
+        OpenExchangeRatesClient::with_url(
+            build_http_client(Duration::from_secs(config.upstream_settings.timeout_seconds))?,
+            app_id,
+            &config.upstream_settings.open_exchange_rates_url,
+        )
+        .with_retry_policy(RetryPolicy::from_settings(&config.retry_settings)),
+    ))
+}
+
+fn build_static_file(config: &Config, _quota: Option<&Arc<UpstreamQuota>>) -> Result<Arc<dyn ExchangeRateClient>, ServiceError> {
+    let path = config.provider_settings.static_rates_path.as_deref().ok_or_else(|| {
+        ServiceError::ConfigError("STATIC_RATES_PATH not set".to_string())
+    })?;
+    Ok(Arc::new(StaticFileClient::from_path(path)?))
+}
+
+/// Re-expresses a rate table quoted against `base` as one quoted against `target`.
+///
+/// Providers such as the ECB only publish a single base, so every other base is
+/// derived by dividing through the target's rate.
+pub fn rebase(
+    base: &str,
+    mut rates: HashMap<String, f64>,
+    target: &str,
+) -> Result<HashMap<String, f64>, ServiceError> {
+    rates.insert(base.to_string(), 1.0);
+    if base == target {
+        return Ok(rates);
+    }
+
+    let pivot = match rates.get(target) {
+        Some(rate) if *rate > 0.0 => *rate,
+        _ => {
+            return Err(ServiceError::InvalidCurrency(format!(
+                "Exchange rates not available for base currency {}", target
+            )))
+        }
+    };
+
+    Ok(rates
+        .into_iter()
+        .map(|(code, rate)| (code, rate / pivot))
+        .collect())
+}
+
+/// Normalizes a single-base rate table into the common response shape.
+pub(crate) fn into_response(
+    base: &str,
+    rates: HashMap<String, f64>,
+    target: &str,
+    time_last_update_utc: Option<String>,
+    source: &str,
+) -> Result<ExchangeRateResponse, ServiceError> {
+    Ok(ExchangeRateResponse {
+        result: "success".to_string(),
+        conversion_rates: rebase(base, rates, target)?,
+        time_last_update_utc,
+        source: source.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebase() {
+        let mut rates = HashMap::new();
+        rates.insert("USD".to_string(), 1.25);
+        rates.insert("JPY".to_string(), 150.0);
+
+        let rebased = rebase("EUR", rates.clone(), "USD").unwrap();
+        assert_eq!(rebased["USD"], 1.0);
+        assert_eq!(rebased["EUR"], 0.8);
+        assert_eq!(rebased["JPY"], 120.0);
+
+        let same = rebase("EUR", rates.clone(), "EUR").unwrap();
+        assert_eq!(same["EUR"], 1.0);
+        assert_eq!(same["JPY"], 150.0);
+
+        assert!(rebase("EUR", rates, "XYZ").is_err());
+    }
+
+    #[test]
+    fn test_provider_registry() {
+        let registry = ProviderRegistry::with_defaults();
+        assert_eq!(
+            registry.names(),
+            vec!["ecb", "exchangerate-api", "open-exchange-rates", "static-file"]
+        );
+
+        let config = Config::default();
+        assert!(registry.build("ecb", &config).is_ok());
+        assert!(registry.build("exchangerate-api", &config).is_err());
+        assert!(registry.build("open-exchange-rates", &config).is_err());
+        assert!(matches!(
+            registry.build("unknown", &config),
+            Err(ServiceError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_chain() {
+        let registry = ProviderRegistry::with_defaults();
+        let config = Config::default();
+        let monitor = Arc::new(UsageMonitor::new());
+        let breakers = CircuitBreakers::new(config.circuit_breaker_settings.clone());
+
+        let chain = registry
+            .build_chain(&["ecb".to_string()], &config, Arc::clone(&monitor), &breakers)
+            .unwrap();
+        assert_eq!(chain.provider_names(), vec!["ecb"]);
+        assert!(breakers.statuses().contains_key("ecb"));
+
+        assert!(registry.build_chain(&[], &config, Arc::clone(&monitor), &breakers).is_err());
+        assert!(registry
+            .build_chain(&["ecb".to_string(), "unknown".to_string()], &config, monitor, &breakers)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_breaker_spends_no_quota() {
+        let config = Config {
+            exchange_rate_api_key: "test_key".to_string(),
+            ..Default::default()
+        };
+        let breakers = CircuitBreakers::new(config.circuit_breaker_settings.clone());
+        let quota = Arc::new(UpstreamQuota::new(100, &config.quota_settings));
+        let chain = ProviderRegistry::with_defaults()
+            .with_quota(Arc::clone(&quota), Arc::new(Cache::new(60, 10)))
+            .build_chain(
+                &[EXCHANGE_RATE_API_PROVIDER.to_string()],
+                &config,
+                Arc::new(UsageMonitor::new()),
+                &breakers,
+            )
+            .unwrap();
+
+        let breaker = breakers.get(EXCHANGE_RATE_API_PROVIDER);
+        for _ in 0..config.circuit_breaker_settings.failure_threshold {
+            let outage = breaker.call(|| async { Err::<(), _>(ServiceError::ServiceUnavailable("down".to_string())) });
+            assert!(outage.await.is_err());
+        }
+
+        for _ in 0..5 {
+            assert!(chain.get_exchange_rate("USD").await.is_err());
+        }
+        assert_eq!(quota.status().await.daily.used, 0);
+    }
+}
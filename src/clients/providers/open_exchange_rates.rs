@@ -0,0 +1,121 @@
+use async_trait::async_trait;
+use crate::clients::{ExchangeRateClient, RetryPolicy};
+use crate::errors::ServiceError;
+use crate::models::ExchangeRateResponse;
+use chrono::DateTime;
+use log::{debug, error};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub const OPEN_EXCHANGE_RATES_URL: &str = "https://openexchangerates.org/api/latest.json";
+pub const OPEN_EXCHANGE_RATES_SOURCE: &str = "openexchangerates.org";
+
+/// Rate table in the Open Exchange Rates `latest.json` layout.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RateDocument {
+    pub base: String,
+    pub rates: HashMap<String, f64>,
+    /// Unix timestamp of the last update
+    pub timestamp: Option<i64>,
+}
+
+impl RateDocument {
+    pub fn time_last_update_utc(&self) -> Option<String> {
+        self.timestamp
+            .and_then(|ts| DateTime::from_timestamp(ts, 0))
+            .map(|dt| dt.to_rfc2822())
+    }
+}
+
+/// Client for Open Exchange Rates and any API sharing its JSON layout.
+pub struct OpenExchangeRatesClient {
+    client: reqwest::Client,
+    app_id: String,
+    url: String,
+    retry_policy: RetryPolicy,
+}
+
+impl OpenExchangeRatesClient {
+    pub fn new(client: reqwest::Client, app_id: String) -> Self {
+        Self::with_url(client, app_id, OPEN_EXCHANGE_RATES_URL)
+    }
+
+    pub fn with_url(client: reqwest::Client, app_id: String, url: impl Into<String>) -> Self {
+        Self {
+            client,
+            app_id,
+            url: url.into(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+}
+
+#[async_trait]
+impl ExchangeRateClient for OpenExchangeRatesClient {
+    async fn get_exchange_rate(
+        &self,
+        from_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        debug!("Fetching Open Exchange Rates table for: {}", from_currency);
+        // Free plans only serve a USD base, so fetch the default table and rebase locally
+        let response = self.retry_policy
+            .send(self.client.get(&self.url).query(&[("app_id", self.app_id.as_str())]))
+            .await?;
+
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            error!("Open Exchange Rates rate limit exceeded");
+            return Err(ServiceError::RateLimitExceeded);
+        }
+
+        if !response.status().is_success() {
+            error!("Open Exchange Rates error: {} for currency: {}", response.status(), from_currency);
+            return Err(ServiceError::ServiceUnavailable(
+                "Open Exchange Rates service unavailable".to_string()
+            ));
+        }
+
+        let document: RateDocument = response
+            .json()
+            .await
+            .map_err(|e| {
+                error!("Failed to parse Open Exchange Rates data: {}", e);
+                ServiceError::ExternalApiError(format!("Failed to parse exchange rate data: {}", e))
+            })?;
+
+        let updated = document.time_last_update_utc();
+        super::into_response(
+            &document.base,
+            document.rates,
+            from_currency,
+            updated,
+            OPEN_EXCHANGE_RATES_SOURCE,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_document_parsing() {
+        let document: RateDocument = serde_json::from_str(r#"{
+            "disclaimer": "Usage subject to terms",
+            "timestamp": 1704153600,
+            "base": "USD",
+            "rates": { "EUR": 0.9, "GBP": 0.8 }
+        }"#).unwrap();
+
+        assert_eq!(document.base, "USD");
+        assert_eq!(document.rates["EUR"], 0.9);
+        assert_eq!(
+            document.time_last_update_utc().as_deref(),
+            Some("Tue, 2 Jan 2024 00:00:00 +0000")
+        );
+    }
+}
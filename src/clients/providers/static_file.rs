@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use crate::clients::ExchangeRateClient;
+use crate::errors::ServiceError;
+use crate::models::ExchangeRateResponse;
+use super::open_exchange_rates::RateDocument;
+use log::debug;
+use std::path::Path;
+
+pub const STATIC_FILE_SOURCE: &str = "static-file";
+
+/// Serves rates from a JSON file in the Open Exchange Rates layout, loaded once at startup.
+pub struct StaticFileClient {
+    document: RateDocument,
+}
+
+impl StaticFileClient {
+    pub fn new(document: RateDocument) -> Self {
+        Self { document }
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ServiceError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            ServiceError::ConfigError(format!("Failed to read rates file {}: {}", path.display(), e))
+        })?;
+        let document = serde_json::from_str(&contents).map_err(|e| {
+            ServiceError::ConfigError(format!("Failed to parse rates file {}: {}", path.display(), e))
+        })?;
+        Ok(Self::new(document))
+    }
+}
+
+#[async_trait]
+impl ExchangeRateClient for StaticFileClient {
+    async fn get_exchange_rate(
+        &self,
+        from_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        debug!("Serving static exchange rates for: {}", from_currency);
+        super::into_response(
+            &self.document.base,
+            self.document.rates.clone(),
+            from_currency,
+            self.document.time_last_update_utc(),
+            STATIC_FILE_SOURCE,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_static_file_client() {
+        let path = std::env::temp_dir().join(format!("rates-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"{"base": "USD", "rates": {"EUR": 0.5, "GBP": 0.25}}"#).unwrap();
+
+        let client = StaticFileClient::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let response = client.get_exchange_rate("EUR").await.unwrap();
+        assert_eq!(response.source, STATIC_FILE_SOURCE);
+        assert_eq!(response.conversion_rates["USD"], 2.0);
+        assert_eq!(response.conversion_rates["GBP"], 0.5);
+
+        assert!(StaticFileClient::from_path(&path).is_err());
+    }
+}
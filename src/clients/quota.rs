@@ -0,0 +1,367 @@
+use async_trait::async_trait;
+use crate::cache::{Cache, ExchangeRateData};
+use crate::clients::ExchangeRateClient;
+use crate::config::QuotaSettings;
+use crate::errors::ServiceError;
+use crate::models::ExchangeRateResponse;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// How freely upstream may still be called, by share of the plan used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaMode {
+    Normal,
+    /// Cached tables are served however old; only cache misses go upstream
+    StaleOnly,
+    /// Nothing goes upstream until the budget resets
+    CacheOnly,
+}
+
+/// Upstream calls counted in the current UTC day and month.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct QuotaCounters {
+    day: NaiveDate,
+    daily_calls: usize,
+    monthly_calls: usize,
+}
+
+impl QuotaCounters {
+    fn new(today: NaiveDate) -> Self {
+        Self {
+            day: today,
+            daily_calls: 0,
+            monthly_calls: 0,
+        }
+    }
+
+    /// Starts a new day, and a new month when it has turned.
+    fn roll_over(&mut self, today: NaiveDate) {
+        if self.day == today {
+            return;
+        }
+        if (self.day.year(), self.day.month()) != (today.year(), today.month()) {
+            self.monthly_calls = 0;
+        }
+        self.day = today;
+        self.daily_calls = 0;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaUsage {
+    pub used: usize,
+    pub limit: usize,
+    pub remaining: usize,
+    pub resets_at: DateTime<Utc>,
+}
+
+impl QuotaUsage {
+    fn new(used: usize, limit: usize, resets_at: NaiveDate) -> Self {
+        Self {
+            used,
+            limit,
+            remaining: limit.saturating_sub(used),
+            resets_at: resets_at.and_time(chrono::NaiveTime::MIN).and_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaStatus {
+    pub mode: QuotaMode,
+    pub daily: QuotaUsage,
+    pub monthly: QuotaUsage,
+    pub stale_only_percent: u8,
+    pub cache_only_percent: u8,
+}
+
+/// Budget of calls against the exchange rate plan, counted per UTC day and
+/// month and saved after every call so a restart does not reset it.
+pub struct UpstreamQuota {
+    daily_limit: usize,
+    monthly_limit: usize,
+    stale_only_percent: u8,
+    cache_only_percent: u8,
+    state_path: Option<PathBuf>,
+    counters: Mutex<QuotaCounters>,
+}
+
+impl UpstreamQuota {
+    pub fn new(daily_limit: usize, settings: &QuotaSettings) -> Self {
+        Self {
+            daily_limit,
+            monthly_limit: settings.requests_per_month,
+            stale_only_percent: settings.stale_only_percent,
+            cache_only_percent: settings.cache_only_percent,
+            state_path: None,
+            counters: Mutex::new(QuotaCounters::new(Utc::now().date_naive())),
+        }
+    }
+
+    /// Keeps the counters in `path`, picking up where a previous run left off.
+    pub fn with_state_path(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str::<QuotaCounters>(&contents) {
+                Ok(mut counters) => {
+                    counters.roll_over(Utc::now().date_naive());
+                    info!(
+                        "Restored upstream quota: {} calls today, {} this month",
+                        counters.daily_calls, counters.monthly_calls
+                    );
+                    self.counters = Mutex::new(counters);
+                }
+                Err(e) => warn!("Ignoring unreadable upstream quota file {}: {}", path.display(), e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to read upstream quota file {}: {}", path.display(), e),
+        }
+        self.state_path = Some(path);
+        self
+    }
+
+    fn mode_for(&self, counters: &QuotaCounters) -> QuotaMode {
+        let percent = |used: usize, limit: usize| match limit {
+            0 => 100,
+            limit => used * 100 / limit,
+        };
+        let used = percent(counters.daily_calls, self.daily_limit)
+            .max(percent(counters.monthly_calls, self.monthly_limit));
+        if used >= usize::from(self.cache_only_percent) {
+            QuotaMode::CacheOnly
+        } else if used >= usize::from(self.stale_only_percent) {
+            QuotaMode::StaleOnly
+        } else {
+            QuotaMode::Normal
+        }
+    }
+
+    pub async fn mode(&self) -> QuotaMode {
+        let mut counters = self.counters.lock().await;
+        counters.roll_over(Utc::now().date_naive());
+        self.mode_for(&counters)
+    }
+
+    pub async fn status(&self) -> QuotaStatus {
+        let mut counters = self.counters.lock().await;
+        let today = Utc::now().date_naive();
+        counters.roll_over(today);
+        let next_month = today.with_day(1).unwrap_or(today) + Months::new(1);
+        QuotaStatus {
+            mode: self.mode_for(&counters),
+            daily: QuotaUsage::new(counters.daily_calls, self.daily_limit, today.succ_opt().unwrap_or(today)),
+            monthly: QuotaUsage::new(counters.monthly_calls, self.monthly_limit, next_month),
+            stale_only_percent: self.stale_only_percent,
+            cache_only_percent: self.cache_only_percent,
+        }
+    }
+
+    /// Counts one upstream call, unless the current mode rules it out.
+    /// `cached` says whether a table, however stale, could be served instead.
+    pub async fn acquire(&self, cached: bool) -> Result<(), ServiceError> {
+        let mut counters = self.counters.lock().await;
+        counters.roll_over(Utc::now().date_naive());
+        match self.mode_for(&counters) {
+            QuotaMode::CacheOnly => {
+                return Err(ServiceError::QuotaExhausted(
+                    "serving cached rates only".to_string(),
+                ))
+            }
+            QuotaMode::StaleOnly if cached => {
+                return Err(ServiceError::QuotaExhausted(
+                    "running low, serving cached rates".to_string(),
+                ))
+            }
+            _ => {}
+        }
+
+        counters.daily_calls += 1;
+        counters.monthly_calls += 1;
+        if counters.daily_calls == self.daily_limit || counters.monthly_calls == self.monthly_limit {
+            warn!("Upstream quota used up: {} calls today, {} this month", counters.daily_calls, counters.monthly_calls);
+        }
+        self.persist(&counters).await;
+        Ok(())
+    }
+
+    /// The vendor refused us, so whatever we counted, today's budget is gone.
+    pub async fn exhaust_today(&self) {
+        let mut counters = self.counters.lock().await;
+        counters.roll_over(Utc::now().date_naive());
+        counters.daily_calls = counters.daily_calls.max(self.daily_limit);
+        warn!("Upstream rate limited us, no more calls until tomorrow");
+        self.persist(&counters).await;
+    }
+
+    /// Replaces the state file atomically; called with the counters locked.
+    async fn persist(&self, counters: &QuotaCounters) {
+        let Some(path) = &self.state_path else { return };
+        let write = async {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            let temp = path.with_extension("tmp");
+            tokio::fs::write(&temp, serde_json::to_vec(counters)?).await?;
+            tokio::fs::rename(&temp, path).await
+        };
+        if let Err(e) = write.await {
+            warn!("Failed to save upstream quota to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Spends the upstream quota on each rate fetch, and refuses fetches the
+/// quota no longer allows so the caller falls back to another provider or its cache.
+///
+/// Only the first attempt is charged here; `HttpClient::with_quota` charges the retries.
+pub struct QuotaGovernedClient {
+    inner: Arc<dyn ExchangeRateClient>,
+    quota: Arc<UpstreamQuota>,
+    cache: Arc<Cache<ExchangeRateData>>,
+}
+
+impl QuotaGovernedClient {
+    pub fn new(
+        inner: Arc<dyn ExchangeRateClient>,
+        quota: Arc<UpstreamQuota>,
+        cache: Arc<Cache<ExchangeRateData>>,
+    ) -> Self {
+        Self { inner, quota, cache }
+    }
+
+    async fn call<F, Fut>(&self, base: &str, call: F) -> Result<ExchangeRateResponse, ServiceError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<ExchangeRateResponse, ServiceError>>,
+    {
+        // Peek, so the check does not count as a cache hit
+        let cached = self.cache.time_to_expiry(base).await.is_some();
+        self.quota.acquire(cached).await?;
+
+        let result = call().await;
+        if matches!(result, Err(ServiceError::RateLimitExceeded)) {
+            self.quota.exhaust_today().await;
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl ExchangeRateClient for QuotaGovernedClient {
+    async fn get_exchange_rate(&self, from_currency: &str) -> Result<ExchangeRateResponse, ServiceError> {
+        self.call(from_currency, || self.inner.get_exchange_rate(from_currency)).await
+    }
+
+    async fn get_exchange_rate_pair(
+        &self,
+        from_currency: &str,
+        to_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        self.call(from_currency, || self.inner.get_exchange_rate_pair(from_currency, to_currency))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::tests::MockClient;
+
+    fn settings(requests_per_month: usize) -> QuotaSettings {
+        QuotaSettings {
+            requests_per_month,
+            stale_only_percent: 50,
+            cache_only_percent: 100,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quota_degrades_to_stale_then_cache_only() {
+        let cache = Arc::new(Cache::new(0, 10).with_stale_window(60));
+        let rates = ExchangeRateResponse {
+            result: "success".to_string(),
+            conversion_rates: [("EUR".to_string(), 0.9)].into_iter().collect(),
+            time_last_update_utc: None,
+            source: "mock".to_string(),
+        };
+        let quota = Arc::new(UpstreamQuota::new(4, &settings(100)));
+        let client = QuotaGovernedClient::new(
+            Arc::new(MockClient::new().with_rate_response(rates.clone())),
+            Arc::clone(&quota),
+            Arc::clone(&cache),
+        );
+
+        assert!(client.get_exchange_rate("USD").await.is_ok());
+        cache.set("USD".to_string(), ExchangeRateData::from_response("USD", rates)).await;
+        assert!(client.get_exchange_rate("USD").await.is_ok());
+
+        // Half the day's budget is gone: cached bases are no longer refreshed
+        assert_eq!(quota.mode().await, QuotaMode::StaleOnly);
+        assert!(matches!(client.get_exchange_rate("USD").await, Err(ServiceError::QuotaExhausted(_))));
+        assert!(client.get_exchange_rate("EUR").await.is_ok());
+        assert!(client.get_exchange_rate("GBP").await.is_ok());
+
+        assert_eq!(quota.mode().await, QuotaMode::CacheOnly);
+        assert!(client.get_exchange_rate("JPY").await.is_err());
+        let status = quota.status().await;
+        assert_eq!((status.daily.used, status.daily.remaining), (4, 0));
+        assert_eq!(status.monthly.used, 4);
+    }
+
+    #[tokio::test]
+    async fn test_stale_only_fetches_tables_past_their_stale_window() {
+        // Expired and past the stale window as soon as it is written
+        let cache = Arc::new(Cache::new(0, 10));
+        let rates = ExchangeRateResponse {
+            result: "success".to_string(),
+            conversion_rates: [("EUR".to_string(), 0.9)].into_iter().collect(),
+            time_last_update_utc: None,
+            source: "mock".to_string(),
+        };
+        cache.set("USD".to_string(), ExchangeRateData::from_response("USD", rates.clone())).await;
+
+        let quota = Arc::new(UpstreamQuota::new(4, &settings(100)));
+        quota.acquire(false).await.unwrap();
+        quota.acquire(false).await.unwrap();
+        assert_eq!(quota.mode().await, QuotaMode::StaleOnly);
+
+        // Nothing could be served instead, so the call goes out
+        let client = QuotaGovernedClient::new(
+            Arc::new(MockClient::new().with_rate_response(rates)),
+            Arc::clone(&quota),
+            cache,
+        );
+        assert!(client.get_exchange_rate("USD").await.is_ok());
+        assert_eq!(quota.status().await.daily.used, 3);
+    }
+
+    #[tokio::test]
+    async fn test_counters_survive_restart() {
+        let path = std::env::temp_dir().join(format!("upstream-quota-{}.json", uuid::Uuid::new_v4()));
+        let quota = UpstreamQuota::new(10, &settings(100)).with_state_path(&path);
+        quota.acquire(false).await.unwrap();
+        quota.acquire(false).await.unwrap();
+
+        let restarted = UpstreamQuota::new(10, &settings(100)).with_state_path(&path);
+        assert_eq!(restarted.status().await.daily.used, 2);
+
+        // A new month clears both counters, a new day only the daily one
+        let mut counters = QuotaCounters {
+            day: NaiveDate::from_ymd_opt(2024, 1, 30).unwrap(),
+            daily_calls: 5,
+            monthly_calls: 50,
+        };
+        counters.roll_over(NaiveDate::from_ymd_opt(2024, 1, 31).unwrap());
+        assert_eq!((counters.daily_calls, counters.monthly_calls), (0, 50));
+        counters.roll_over(NaiveDate::from_ymd_opt(2024, 2, 1).unwrap());
+        assert_eq!((counters.daily_calls, counters.monthly_calls), (0, 0));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::config::RetrySettings;
+use log::warn;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Transport failures that may be retried, as reported by reqwest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryErrorKind {
+    /// No response within the configured timeout
+    Timeout,
+    /// The connection could not be established
+    Connect,
+    /// The connection failed after it was established, e.g. reset or closed
+    /// before a response arrived
+    Request,
+    /// Sending the request body or reading the response failed
+    Body,
+}
+
+impl RetryErrorKind {
+    fn matches(self, error: &reqwest::Error) -> bool {
+        match self {
+            Self::Timeout => error.is_timeout(),
+            Self::Connect => error.is_connect(),
+            Self::Request => error.is_request() && !error.is_timeout() && !error.is_connect(),
+            Self::Body => error.is_body() || error.is_decode(),
+        }
+    }
+}
+
+impl FromStr for RetryErrorKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "timeout" => Ok(Self::Timeout),
+            "connect" => Ok(Self::Connect),
+            "request" => Ok(Self::Request),
+            "body" => Ok(Self::Body),
+            other => Err(format!("Unknown retry error kind: {}", other)),
+        }
+    }
+}
+
+/// Decides whether and when a failed upstream call is attempted again.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Fraction of each delay, between 0.0 and 1.0, that is randomized
+    pub jitter: f64,
+    pub retryable_status_codes: Vec<u16>,
+    pub retryable_error_kinds: Vec<RetryErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::from_settings(&RetrySettings::default())
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that sends every request exactly once.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    pub fn from_settings(settings: &RetrySettings) -> Self {
+        Self {
+            max_attempts: settings.max_attempts.max(1),
+            base_delay: Duration::from_millis(settings.base_delay_ms),
+            max_delay: Duration::from_millis(settings.max_delay_ms),
+            jitter: settings.jitter.clamp(0.0, 1.0),
+            retryable_status_codes: settings.retryable_status_codes.clone(),
+            retryable_error_kinds: settings.retryable_error_kinds.clone(),
+        }
+    }
+
+    /// 404 and 429 are final answers from upstream and are never retried.
+    pub fn is_retryable_status(&self, status: StatusCode) -> bool {
+        status != StatusCode::NOT_FOUND
+            && status != StatusCode::TOO_MANY_REQUESTS
+            && self.retryable_status_codes.contains(&status.as_u16())
+    }
+
+    pub fn is_retryable_error(&self, error: &reqwest::Error) -> bool {
+        self.retryable_error_kinds.iter().any(|kind| kind.matches(error))
+    }
+
+    /// Exponential backoff for the given retry (0 for the first retry), capped and jittered.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(retry));
+        let capped = exponential.min(self.max_delay);
+        capped.mul_f64(1.0 - self.jitter * random_fraction())
+    }
+
+    /// Sends the request, retrying transient failures according to the policy.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        self.send_with(request, || async { true }).await
+    }
+
+    /// Like `send`, but asks `allow_retry` before every retry, and returns the
+    /// last failure as is when it says no. Used to charge retries to a budget.
+    pub async fn send_with<F, Fut>(&self, request: RequestBuilder, mut allow_retry: F) -> Result<Response, reqwest::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        let mut attempt = 1;
+        loop {
+            // Bodies that cannot be cloned can only be sent once
+            let retry_request = if attempt < self.max_attempts { request.try_clone() } else { None };
+            let current = match retry_request {
+                Some(clone) => clone,
+                None => return request.send().await,
+            };
+
+            let result = current.send().await;
+            match &result {
+                Ok(response) if self.is_retryable_status(response.status()) => {
+                    warn!(
+                        "Upstream returned {} for {} (attempt {}/{}), retrying",
+                        response.status(), response.url(), attempt, self.max_attempts
+                    );
+                }
+                Err(e) if self.is_retryable_error(e) => {
+                    warn!("Upstream request failed (attempt {}/{}), retrying: {}", attempt, self.max_attempts, e);
+                }
+                _ => return result,
+            }
+            if !allow_retry().await {
+                return result;
+            }
+
+            tokio::time::sleep(self.backoff(attempt - 1)).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Uniformly distributed value in [0, 1) for jitter.
+fn random_fraction() -> f64 {
+    let (bits, _) = uuid::Uuid::new_v4().as_u64_pair();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn policy(jitter: f64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(250),
+            jitter,
+            retryable_status_codes: vec![404, 429, 503],
+            retryable_error_kinds: vec![RetryErrorKind::Timeout, RetryErrorKind::Connect, RetryErrorKind::Request],
+        }
+    }
+
+    /// Serves the given status codes in order, one per connection.
+    fn serve_statuses(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        std::thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = write!(stream, "HTTP/1.1 {} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+            }
+        });
+        (url, hits)
+    }
+
+    /// Accepts `drops` connections and closes each without answering, then
+    /// serves a 200.
+    fn serve_after_drops(drops: usize) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(drops + 1) {
+                let mut stream = stream.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                if counter.fetch_add(1, Ordering::SeqCst) < drops {
+                    continue;
+                }
+                let _ = write!(stream, "HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+            }
+        });
+        (url, hits)
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = policy(0.0);
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(5), Duration::from_millis(250));
+
+        let jittered = self::policy(0.5).backoff(1);
+        assert!(jittered > Duration::from_millis(100) && jittered <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_never_retries_not_found_or_rate_limited() {
+        let policy = policy(0.0);
+        assert!(policy.is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!policy.is_retryable_status(StatusCode::NOT_FOUND));
+        assert!(!policy.is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!policy.is_retryable_status(StatusCode::BAD_GATEWAY));
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_transient_status() {
+        let (url, hits) = serve_statuses(vec![503, 503, 200]);
+        let mut policy = policy(0.0);
+        policy.base_delay = Duration::from_millis(1);
+
+        let response = policy.send(reqwest::Client::new().get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        let (url, hits) = serve_statuses(vec![429, 200]);
+        let response = policy.send(reqwest::Client::new().get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // A refused retry hands back the failure it would have retried
+        let (url, hits) = serve_statuses(vec![503, 503, 200]);
+        let mut allowed = 1;
+        let response = policy
+            .send_with(reqwest::Client::new().get(&url), || {
+                allowed -= 1;
+                async move { allowed >= 0 }
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_dropped_connection() {
+        let mut policy = policy(0.0);
+        policy.base_delay = Duration::from_millis(1);
+
+        let (url, hits) = serve_after_drops(2);
+        let response = policy.send(reqwest::Client::new().get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // Without the request kind, a dropped connection is final
+        policy.retryable_error_kinds.retain(|kind| *kind != RetryErrorKind::Request);
+        let (url, hits) = serve_after_drops(1);
+        let error = policy.send(reqwest::Client::new().get(&url)).await.unwrap_err();
+        assert!(error.is_request() && !error.is_connect());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_error_kinds_parse() {
+        assert_eq!(" Request ".parse::<RetryErrorKind>(), Ok(RetryErrorKind::Request));
+        assert!("reset".parse::<RetryErrorKind>().is_err());
+    }
+}
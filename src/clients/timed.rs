@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use crate::clients::{CountryClient, ExchangeRateClient};
+use crate::errors::ServiceError;
+use crate::models::{CountryInfo, ExchangeRateResponse};
+use crate::monitor::UsageMonitor;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Records how long every call to an upstream dependency takes, failed calls
+/// included, under the dependency's name in the monitor's rolling latencies.
+///
+/// Wrap the client that makes the HTTP calls, below any cache, circuit breaker
+/// or fallback, so only calls that leave the service are timed.
+pub struct TimedClient<C: ?Sized> {
+    inner: Arc<C>,
+    dependency: &'static str,
+    provider: Option<String>,
+    monitor: Arc<UsageMonitor>,
+}
+
+impl<C: ?Sized> TimedClient<C> {
+    pub fn new(inner: Arc<C>, dependency: &'static str, monitor: Arc<UsageMonitor>) -> Self {
+        Self {
+            inner,
+            dependency,
+            provider: None,
+            monitor,
+        }
+    }
+
+    /// Also reports every call as an attempt on `provider` in the upstream
+    /// metrics and usage stats.
+    pub fn with_provider(mut self, provider: impl Into<String>) -> Self {
+        self.provider = Some(provider.into());
+        self
+    }
+
+    /// A call fails when upstream could not answer; answers such as an unknown
+    /// country or currency still count as successful calls.
+    async fn record<T>(&self, started: Instant, result: &Result<T, ServiceError>) {
+        let elapsed = started.elapsed();
+        self.monitor.record_upstream_latency(self.dependency, elapsed).await;
+        if let Some(provider) = &self.provider {
+            let failed = matches!(
+                result,
+                Err(ServiceError::ExternalApiError(_) | ServiceError::ServiceUnavailable(_) | ServiceError::RateLimitExceeded)
+            );
+            self.monitor.record_provider_attempt(provider, !failed, elapsed).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<C: CountryClient + ?Sized> CountryClient for TimedClient<C> {
+    async fn get_country_info(&self, country_name: &str) -> Result<CountryInfo, ServiceError> {
+        let started = Instant::now();
+        let result = self.inner.get_country_info(country_name).await;
+        self.record(started, &result).await;
+        result
+    }
+}
+
+#[async_trait]
+impl<C: ExchangeRateClient + ?Sized> ExchangeRateClient for TimedClient<C> {
+    async fn get_exchange_rate(
+        &self,
+        from_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        let started = Instant::now();
+        let result = self.inner.get_exchange_rate(from_currency).await;
+        self.record(started, &result).await;
+        result
+    }
+
+    async fn get_exchange_rate_pair(
+        &self,
+        from_currency: &str,
+        to_currency: &str,
+    ) -> Result<ExchangeRateResponse, ServiceError> {
+        let started = Instant::now();
+        let result = self.inner.get_exchange_rate_pair(from_currency, to_currency).await;
+        self.record(started, &result).await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::circuit_breaker::CircuitBreaker;
+    use crate::clients::tests::MockClient;
+    use crate::clients::CircuitBreakerClient;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_breaker_refusals_are_not_upstream_attempts() {
+        let monitor = Arc::new(UsageMonitor::new());
+        let timed = TimedClient::new(Arc::new(MockClient::new()), "rates", Arc::clone(&monitor)).with_provider("down");
+        let breaker = Arc::new(CircuitBreaker::new("down", 1, Duration::from_secs(60)));
+        let client = CircuitBreakerClient::new(Arc::new(timed), breaker);
+
+        // The first call reaches the provider and opens the breaker, which refuses the rest
+        for _ in 0..3 {
+            assert!(client.get_exchange_rate("USD").await.is_err());
+        }
+
+        let stats = monitor.get_stats().await;
+        assert_eq!((stats.providers["down"].attempts, stats.providers["down"].failures), (1, 1));
+        assert_eq!(monitor.http_metrics().await.upstream_latency["down"].count, 1);
+        assert_eq!(stats.latency.upstream["rates"]["1m"].count, 1);
+    }
+}
@@ -1,39 +1,499 @@
+use crate::cache::EvictionPolicy;
+use crate::cache_backend::CacheBackendKind;
+use crate::persistence::SnapshotFormat;
+use crate::rate_limit::LimitRule;
+use crate::clients::{
+    self,
+    RetryErrorKind,
+    providers::{ecb::ECB_DAILY_FEED_URL, open_exchange_rates::OPEN_EXCHANGE_RATES_URL},
+};
+use std::collections::HashMap;
 use std::env;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Config {
     pub exchange_rate_api_key: String,
     pub cache_settings: CacheSettings,
     pub rate_limit_settings: RateLimitSettings,
+    pub quota_settings: QuotaSettings,
+    pub client_rate_limit_settings: ClientRateLimitSettings,
+    pub auth_settings: AuthSettings,
+    pub provider_settings: ProviderSettings,
+    pub retry_settings: RetrySettings,
+    pub circuit_breaker_settings: CircuitBreakerSettings,
+    pub upstream_settings: UpstreamSettings,
+    pub persistence_settings: PersistenceSettings,
+    pub refresh_settings: RefreshSettings,
 }
 
 #[derive(Debug, Clone)]
 pub struct CacheSettings {
     pub exchange_rate_ttl_minutes: i64,    // 60 minutes based on your plan
     pub country_info_ttl_minutes: i64,     // 24 hours since this rarely changes
+    pub country_not_found_ttl_minutes: i64,// Unknown country names are remembered for less time
     pub cache_cleanup_interval_minutes: i64,// Cleanup old cache entries
+    pub eviction_policy: EvictionPolicy,    // Which entry a full cache drops for a new key
+    pub stale_window_minutes: i64,          // How long expired rates may still be served
+    pub backend: CacheBackendKind,          // In-process map, or Redis shared by replicas
+    pub redis_url: Option<String>,          // Only needed for the redis backend
+    pub redis_key_prefix: String,           // Namespace for this service's keys in Redis
 }
 
 #[derive(Debug, Clone)]
 pub struct RateLimitSettings {
     pub requests_per_day: usize,     // 30,000 per month ≈ 1,000 per day
-    pub window_size_minutes: i64,    // Time window for rate limiting
+    pub window_size_minutes: i64,    // Window for client limits given as a bare count
+}
+
+#[derive(Debug, Clone)]
+pub struct QuotaSettings {
+    pub enabled: bool,                  // Track calls against the exchangerate-api plan
+    pub requests_per_month: usize,      // Monthly plan; the daily budget is requests_per_day
+    pub stale_only_percent: u8,         // Past this share, cached tables are no longer refreshed
+    pub cache_only_percent: u8,         // Past this share, upstream is not called at all
+    pub state_path: Option<String>,     // Counters file, by default next to the cache snapshots
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientRateLimitSettings {
+    pub enabled: bool,                          // Limit incoming requests per client
+    pub scope_limits: HashMap<String, Vec<LimitRule>>, // Stacked limits per client, by route scope
+    pub trust_forwarded_for: bool,              // Key on X-Forwarded-For when behind a proxy
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuthSettings {
+    pub api_keys_file: Option<String>,  // Tiers and client API keys; endpoints are open when unset
+    pub admin_api_key: Option<String>,  // Operator key for /admin, on top of tiers that include it
+}
+
+#[derive(Debug, Clone)]
+pub struct ProviderSettings {
+    pub exchange_rate_provider: String,              // Name of the provider serving exchange rates
+    pub fallback_providers: Vec<String>,             // Tried in order when the primary fails
+    pub open_exchange_rates_app_id: Option<String>,  // Only needed for open-exchange-rates
+    pub static_rates_path: Option<String>,           // Only needed for static-file
+    pub country_source: String,                      // "restcountries" or the bundled "offline" dataset
+    pub offline_country_fallback: bool,              // Use the bundled dataset when restcountries fails
+}
+
+#[derive(Debug, Clone)]
+pub struct RetrySettings {
+    pub max_attempts: u32,                   // Including the first attempt
+    pub base_delay_ms: u64,                  // Delay before the first retry, doubled each time
+    pub max_delay_ms: u64,                   // Upper bound for a single delay
+    pub jitter: f64,                         // Fraction of each delay that is randomized
+    pub retryable_status_codes: Vec<u16>,    // 404 and 429 are never retried
+    pub retryable_error_kinds: Vec<RetryErrorKind>, // Transport failures that are retried
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerSettings {
+    pub failure_threshold: u32,    // Consecutive failures before the circuit opens
+    pub cooldown_seconds: u64,     // Time the circuit stays open before probing again
+}
+
+#[derive(Debug, Clone)]
+pub struct UpstreamSettings {
+    pub rest_countries_base_url: String,       // e.g. an internal mirror or a local fake
+    pub exchange_rate_api_base_url: String,
+    pub ecb_feed_url: String,
+    pub open_exchange_rates_url: String,
+    pub timeout_seconds: u64,                  // Per attempt, for both connecting and the whole request
+}
+
+#[derive(Debug, Clone)]
+pub struct PersistenceSettings {
+    pub snapshot_dir: Option<String>,       // Cache snapshots are disabled when unset
+    pub snapshot_format: SnapshotFormat,    // json or jsonl
+    pub snapshot_interval_minutes: u64,     // How often caches are written while running
+}
+
+#[derive(Debug, Clone)]
+pub struct RefreshSettings {
+    pub enabled: bool,
+    pub max_bases: usize,              // Most requested base currencies kept warm
+    pub refresh_ahead_minutes: i64,    // Refresh tables expiring within this window
+    pub check_interval_seconds: u64,   // How often popular tables are checked
+    pub daily_budget: usize,           // Upstream calls per day, capped at requests_per_day
+    pub popularity_half_life_minutes: i64, // Lookups older than this count half as much
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            exchange_rate_ttl_minutes: 60,          // Match API update frequency
+            country_info_ttl_minutes: 24 * 60,     // 24 hours
+            country_not_found_ttl_minutes: 10,      // Short, so a newly recognized name shows up soon
+            cache_cleanup_interval_minutes: 5,      // Clean every 5 minutes
+            eviction_policy: EvictionPolicy::Lru,
+            stale_window_minutes: 24 * 60,         // Yesterday's rates beat an error
+            backend: CacheBackendKind::Memory,
+            redis_url: None,
+            redis_key_prefix: "currency-converter".to_string(),
+        }
+    }
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            requests_per_day: 1000,                // ~30,000 per month
+            window_size_minutes: 24 * 60,          // 24 hour window
+        }
+    }
+}
+
+impl Default for QuotaSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            requests_per_month: 30_000,
+            stale_only_percent: 80,
+            cache_only_percent: 95,            // Leave a little for the vendor's own counting
+            state_path: None,
+        }
+    }
+}
+
+impl Default for ClientRateLimitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            scope_limits: ["/currency", "/v1"]
+                .into_iter()
+                .map(|scope| {
+                    // Short bursts are fine, a day's allowance in one second is not
+                    let rules = vec![
+                        LimitRule::TokenBucket { burst: 20, refill_per_second: 5.0 },
+                        LimitRule::per_day(1000),
+                    ];
+                    (scope.to_string(), rules)
+                })
+                .collect(),
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+impl ClientRateLimitSettings {
+    /// Parses `scope=rules` pairs such as `/currency=10/s+1000/1d,/v1=bucket:20@5/s`.
+    /// Rules joined with `+` stack; a bare count such as `/v1=500` means that many
+    /// requests per `default_window`.
+    pub fn parse_scope_limits(
+        value: &str,
+        default_window: chrono::Duration,
+    ) -> Result<HashMap<String, Vec<LimitRule>>, String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (scope, rules) = pair
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid client rate limit: {}", pair))?;
+                let rules = rules
+                    .split('+')
+                    .map(|rule| match rule.trim().parse() {
+                        Ok(limit) => Ok(LimitRule::per_window(limit, default_window)),
+                        Err(_) => rule.parse(),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok((scope.trim().to_string(), rules))
+            })
+            .collect()
+    }
+}
+
+impl Default for ProviderSettings {
+    fn default() -> Self {
+        Self {
+            exchange_rate_provider: "exchangerate-api".to_string(),
+            fallback_providers: Vec::new(),
+            open_exchange_rates_app_id: None,
+            static_rates_path: None,
+            country_source: "restcountries".to_string(),
+            offline_country_fallback: true,
+        }
+    }
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 200,
+            max_delay_ms: 2_000,
+            jitter: 0.5,
+            retryable_status_codes: vec![500, 502, 503, 504],
+            retryable_error_kinds: vec![RetryErrorKind::Timeout, RetryErrorKind::Connect, RetryErrorKind::Request],
+        }
+    }
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown_seconds: 30,
+        }
+    }
+}
+
+impl Default for UpstreamSettings {
+    fn default() -> Self {
+        Self {
+            rest_countries_base_url: clients::REST_COUNTRIES_BASE_URL.to_string(),
+            exchange_rate_api_base_url: clients::EXCHANGE_RATE_API_BASE_URL.to_string(),
+            ecb_feed_url: ECB_DAILY_FEED_URL.to_string(),
+            open_exchange_rates_url: OPEN_EXCHANGE_RATES_URL.to_string(),
+            timeout_seconds: 30,
+        }
+    }
+}
+
+impl Default for PersistenceSettings {
+    fn default() -> Self {
+        Self {
+            snapshot_dir: None,
+            snapshot_format: SnapshotFormat::Json,
+            snapshot_interval_minutes: 10,
+        }
+    }
+}
+
+impl Default for RefreshSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_bases: 10,
+            refresh_ahead_minutes: 5,
+            check_interval_seconds: 60,
+            daily_budget: 250,                     // A quarter of the daily plan
+            popularity_half_life_minutes: 60,
+        }
+    }
+}
+
+impl ProviderSettings {
+    /// Primary provider followed by the fallbacks, in the order they are tried.
+    pub fn provider_chain(&self) -> Vec<String> {
+        let mut chain = vec![self.exchange_rate_provider.clone()];
+        chain.extend(self.fallback_providers.iter().cloned());
+        chain
+    }
+}
+
+/// Parses `name` from the environment, or returns `default` when it is unset.
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String> {
+    match env::var(name) {
+        Ok(value) => value.parse().map_err(|_| format!("Invalid {}: {}", name, value)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Reads a `true`/`false` (or `1`/`0`) switch, keeping `default` for anything else.
+fn env_flag(name: &str, default: bool) -> bool {
+    match env::var(name).as_deref() {
+        Ok("true") | Ok("1") => true,
+        Ok("false") | Ok("0") => false,
+        _ => default,
+    }
 }
 
 impl Config {
     pub fn new() -> Result<Self, String> {
-        Ok(Self {
-            exchange_rate_api_key: env::var("EXCHANGE_RATE_API_KEY")
-                .map_err(|_| "EXCHANGE_RATE_API_KEY not set")?,
-            cache_settings: CacheSettings {
-                exchange_rate_ttl_minutes: 60,          // Match API update frequency
-                country_info_ttl_minutes: 24 * 60,     // 24 hours
-                cache_cleanup_interval_minutes: 5,      // Clean every 5 minutes
+        let provider_settings = ProviderSettings {
+            exchange_rate_provider: env::var("EXCHANGE_RATE_PROVIDER")
+                .unwrap_or_else(|_| ProviderSettings::default().exchange_rate_provider),
+            fallback_providers: env::var("EXCHANGE_RATE_FALLBACK_PROVIDERS")
+                .map(|list| {
+                    list.split(',')
+                        .map(|name| name.trim().to_string())
+                        .filter(|name| !name.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            open_exchange_rates_app_id: env::var("OPEN_EXCHANGE_RATES_APP_ID").ok(),
+            static_rates_path: env::var("STATIC_RATES_PATH").ok(),
+            country_source: env::var("COUNTRY_SOURCE")
+                .unwrap_or_else(|_| ProviderSettings::default().country_source),
+            offline_country_fallback: env::var("OFFLINE_COUNTRY_FALLBACK")
+                .map(|value| value != "false" && value != "0")
+                .unwrap_or(true),
+        };
+
+        // The API key is only mandatory when exchangerate-api.com serves the rates
+        let exchange_rate_api_key = match env::var("EXCHANGE_RATE_API_KEY") {
+            Ok(key) => key,
+            Err(_) if !provider_settings.provider_chain().iter().any(|p| p == "exchangerate-api") => {
+                String::new()
+            }
+            Err(_) => return Err("EXCHANGE_RATE_API_KEY not set".to_string()),
+        };
+
+        let defaults = UpstreamSettings::default();
+        let upstream_settings = UpstreamSettings {
+            rest_countries_base_url: env::var("REST_COUNTRIES_BASE_URL")
+                .unwrap_or(defaults.rest_countries_base_url),
+            exchange_rate_api_base_url: env::var("EXCHANGE_RATE_API_BASE_URL")
+                .unwrap_or(defaults.exchange_rate_api_base_url),
+            ecb_feed_url: env::var("ECB_FEED_URL")
+                .unwrap_or(defaults.ecb_feed_url),
+            open_exchange_rates_url: env::var("OPEN_EXCHANGE_RATES_URL")
+                .unwrap_or(defaults.open_exchange_rates_url),
+            timeout_seconds: env_or("UPSTREAM_TIMEOUT_SECONDS", defaults.timeout_seconds)?,
+        };
+
+        let defaults = CircuitBreakerSettings::default();
+        let circuit_breaker_settings = CircuitBreakerSettings {
+            failure_threshold: env_or("CIRCUIT_BREAKER_FAILURE_THRESHOLD", defaults.failure_threshold)?,
+            cooldown_seconds: env_or("CIRCUIT_BREAKER_COOLDOWN_SECONDS", defaults.cooldown_seconds)?,
+        };
+
+        let defaults = RetrySettings::default();
+        let mut retry_settings = RetrySettings {
+            max_attempts: env_or("RETRY_MAX_ATTEMPTS", defaults.max_attempts)?,
+            base_delay_ms: env_or("RETRY_BASE_DELAY_MS", defaults.base_delay_ms)?,
+            max_delay_ms: env_or("RETRY_MAX_DELAY_MS", defaults.max_delay_ms)?,
+            jitter: env_or("RETRY_JITTER", defaults.jitter)?,
+            retryable_status_codes: match env::var("RETRY_STATUS_CODES") {
+                Ok(codes) => codes
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|code| !code.is_empty())
+                    .map(|code| code.parse().map_err(|_| format!("Invalid RETRY_STATUS_CODES: {}", codes)))
+                    .collect::<Result<_, _>>()?,
+                Err(_) => defaults.retryable_status_codes,
+            },
+            retryable_error_kinds: match env::var("RETRY_ERROR_KINDS") {
+                Ok(kinds) => kinds
+                    .split(',')
+                    .filter(|kind| !kind.trim().is_empty())
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?,
+                Err(_) => defaults.retryable_error_kinds,
             },
-            rate_limit_settings: RateLimitSettings {
-                requests_per_day: 1000,                // ~30,000 per month
-                window_size_minutes: 24 * 60,          // 24 hour window
+        };
+        // The older switches still turn off a single kind
+        if !env_flag("RETRY_ON_TIMEOUT", true) {
+            retry_settings.retryable_error_kinds.retain(|kind| *kind != RetryErrorKind::Timeout);
+        }
+        if !env_flag("RETRY_ON_CONNECT_ERROR", true) {
+            retry_settings.retryable_error_kinds.retain(|kind| *kind != RetryErrorKind::Connect);
+        }
+
+        let defaults = CacheSettings::default();
+        let cache_settings = CacheSettings {
+            eviction_policy: match env::var("CACHE_EVICTION_POLICY") {
+                Ok(policy) => policy.parse()?,
+                Err(_) => EvictionPolicy::default(),
+            },
+            backend: match env::var("CACHE_BACKEND") {
+                Ok(backend) => backend.parse()?,
+                Err(_) => CacheBackendKind::default(),
+            },
+            redis_url: env::var("REDIS_URL").ok().filter(|url| !url.is_empty()),
+            redis_key_prefix: env::var("REDIS_KEY_PREFIX").unwrap_or(defaults.redis_key_prefix),
+            ..defaults
+        };
+        if cache_settings.backend == CacheBackendKind::Redis && cache_settings.redis_url.is_none() {
+            return Err("REDIS_URL must be set when CACHE_BACKEND is redis".to_string());
+        }
+
+        let persistence_settings = PersistenceSettings {
+            snapshot_dir: env::var("CACHE_SNAPSHOT_DIR").ok().filter(|dir| !dir.is_empty()),
+            snapshot_format: match env::var("CACHE_SNAPSHOT_FORMAT") {
+                Ok(format) => format.parse()?,
+                Err(_) => SnapshotFormat::default(),
+            },
+            snapshot_interval_minutes: match env::var("CACHE_SNAPSHOT_INTERVAL_MINUTES") {
+                Ok(minutes) => minutes
+                    .parse()
+                    .map_err(|_| format!("Invalid CACHE_SNAPSHOT_INTERVAL_MINUTES: {}", minutes))?,
+                Err(_) => PersistenceSettings::default().snapshot_interval_minutes,
             },
+        };
+
+        let defaults = RefreshSettings::default();
+        let refresh_settings = RefreshSettings {
+            enabled: env::var("BACKGROUND_REFRESH_ENABLED")
+                .map(|value| value != "false" && value != "0")
+                .unwrap_or(defaults.enabled),
+            daily_budget: match env::var("BACKGROUND_REFRESH_DAILY_BUDGET") {
+                Ok(budget) => budget
+                    .parse()
+                    .map_err(|_| format!("Invalid BACKGROUND_REFRESH_DAILY_BUDGET: {}", budget))?,
+                Err(_) => defaults.daily_budget,
+            },
+            popularity_half_life_minutes: env_or(
+                "BACKGROUND_REFRESH_HALF_LIFE_MINUTES",
+                defaults.popularity_half_life_minutes,
+            )?,
+            ..defaults
+        };
+
+        let defaults = RateLimitSettings::default();
+        let rate_limit_settings = RateLimitSettings {
+            requests_per_day: match env::var("UPSTREAM_REQUESTS_PER_DAY") {
+                Ok(limit) => limit
+                    .parse()
+                    .map_err(|_| format!("Invalid UPSTREAM_REQUESTS_PER_DAY: {}", limit))?,
+                Err(_) => defaults.requests_per_day,
+            },
+            ..defaults
+        };
+
+        let defaults = QuotaSettings::default();
+        let quota_settings = QuotaSettings {
+            enabled: env::var("UPSTREAM_QUOTA_ENABLED")
+                .map(|value| value != "false" && value != "0")
+                .unwrap_or(defaults.enabled),
+            requests_per_month: match env::var("UPSTREAM_REQUESTS_PER_MONTH") {
+                Ok(limit) => limit
+                    .parse()
+                    .map_err(|_| format!("Invalid UPSTREAM_REQUESTS_PER_MONTH: {}", limit))?,
+                Err(_) => defaults.requests_per_month,
+            },
+            state_path: env::var("UPSTREAM_QUOTA_STATE_PATH").ok().filter(|path| !path.is_empty()),
+            ..defaults
+        };
+
+        let defaults = ClientRateLimitSettings::default();
+        let client_rate_limit_settings = ClientRateLimitSettings {
+            enabled: env::var("CLIENT_RATE_LIMIT_ENABLED")
+                .map(|value| value != "false" && value != "0")
+                .unwrap_or(defaults.enabled),
+            scope_limits: match env::var("CLIENT_RATE_LIMITS") {
+                Ok(limits) => ClientRateLimitSettings::parse_scope_limits(
+                    &limits,
+                    chrono::Duration::minutes(rate_limit_settings.window_size_minutes),
+                )?,
+                Err(_) => defaults.scope_limits,
+            },
+            trust_forwarded_for: env::var("TRUST_FORWARDED_FOR")
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(defaults.trust_forwarded_for),
+        };
+
+        let auth_settings = AuthSettings {
+            api_keys_file: env::var("API_KEYS_FILE").ok().filter(|path| !path.is_empty()),
+            admin_api_key: env::var("ADMIN_API_KEY").ok().filter(|key| !key.is_empty()),
+        };
+
+        Ok(Self {
+            exchange_rate_api_key,
+            cache_settings,
+            rate_limit_settings,
+            quota_settings,
+            client_rate_limit_settings,
+            auth_settings,
+            persistence_settings,
+            refresh_settings,
+            provider_settings,
+            upstream_settings,
+            retry_settings,
+            circuit_breaker_settings,
         })
     }
-}
\ No newline at end of file
+}
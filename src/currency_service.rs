@@ -2,32 +2,149 @@ use crate::{
     models::*,
     errors::ServiceError,
     clients::{CountryClient, ExchangeRateClient},
-    cache::{Cache, ExchangeRateData}
+    cache::{Cache, CachedValue, ExchangeRateData},
+    monitor::UsageMonitor,
+    refresh::BasePopularity,
+    single_flight::SingleFlight,
+    format_country_name, round_to_cents,
 };
-use chrono::{DateTime, Utc};
-use log::{debug, error, info};
-use std::sync::Arc;
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, error, info, warn};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
-pub struct CurrencyService<C>
-where
-    C: CountryClient + ExchangeRateClient,
-{
-    client: C,
+/// Outcome of a rate lookup, including where the rate came from.
+struct RateQuote {
+    converted_amount: f64,
+    rate: f64,
+    last_updated: DateTime<Utc>,
+    source: String,
+    cache_hit: bool,
+    /// Base currency whose table the rate was triangulated through
+    pivot: Option<String>,
+    /// Served past its TTL, either while revalidating or because upstream failed
+    stale: bool,
+    /// Time since the table was fetched, for cached rates
+    age: Option<Duration>,
+}
+
+/// Loads rate tables from upstream into the cache, sharing one upstream call
+/// among concurrent requests for the same base currency.
+#[derive(Clone)]
+struct TableFetcher {
+    rate_client: Arc<dyn ExchangeRateClient>,
     cache: Arc<Cache<ExchangeRateData>>,
+    flights: Arc<SingleFlight<ExchangeRateData>>,
+    monitor: Option<Arc<UsageMonitor>>,
 }
 
-impl<C> CurrencyService<C>
-where
-    C: CountryClient + ExchangeRateClient,
-{
-    pub fn new(client: C, cache: Arc<Cache<ExchangeRateData>>) -> Self {
-        Self { client, cache }
+impl TableFetcher {
+    /// Fetches the table for `base`, or joins a fetch already in flight for it.
+    /// `target` must be quoted, so providers lacking it can be skipped.
+    async fn fetch(&self, base: &str, target: Option<&str>) -> Result<ExchangeRateData, ServiceError> {
+        let (result, coalesced) = self.flights.run(base, || self.load(base, target)).await;
+        if !coalesced {
+            return result;
+        }
+
+        debug!("Joined in-flight rate fetch for {}", base);
+        if let Some(monitor) = &self.monitor {
+            monitor.record_coalesced_wait().await;
+        }
+        match (result, target) {
+            // The shared call was made for a different target currency
+            (Ok(table), Some(target)) if table.rate(target).is_none() => self.load(base, Some(target)).await,
+            (Err(ServiceError::InvalidCurrency(_)), Some(target)) => self.load(base, Some(target)).await,
+            (result, _) => result,
+        }
+    }
+
+    async fn load(&self, base: &str, target: Option<&str>) -> Result<ExchangeRateData, ServiceError> {
+        if let Some(monitor) = &self.monitor {
+            monitor.record_api_call().await;
+        }
+        let response = match target {
+            Some(target) => self.rate_client.get_exchange_rate_pair(base, target).await?,
+            None => self.rate_client.get_exchange_rate(base).await?,
+        };
+        let table = ExchangeRateData::from_response(base, response);
+
+        // Cache the whole table for the base currency
+        self.cache.set(base.to_string(), table.clone()).await;
+        Ok(table)
+    }
+}
+
+pub struct CurrencyService {
+    country_client: Arc<dyn CountryClient>,
+    cache: Arc<Cache<ExchangeRateData>>,
+    fetcher: TableFetcher,
+    // Base currencies with a background refresh in progress
+    refreshing: Arc<Mutex<HashSet<String>>>,
+    popularity: Option<Arc<BasePopularity>>,
+}
+
+impl CurrencyService {
+    pub fn new<C>(client: C, cache: Arc<Cache<ExchangeRateData>>) -> Self
+    where
+        C: CountryClient + ExchangeRateClient + 'static,
+    {
+        let client = Arc::new(client);
+        Self::with_clients(client.clone(), client, cache)
+    }
+
+    /// Builds a service from independent country and exchange rate clients,
+    /// so each lookup can be backed by a different implementation.
+    pub fn with_clients(
+        country_client: Arc<dyn CountryClient>,
+        rate_client: Arc<dyn ExchangeRateClient>,
+        cache: Arc<Cache<ExchangeRateData>>,
+    ) -> Self {
+        Self {
+            country_client,
+            fetcher: TableFetcher {
+                rate_client,
+                cache: Arc::clone(&cache),
+                flights: Arc::new(SingleFlight::new()),
+                monitor: None,
+            },
+            cache,
+            refreshing: Arc::new(Mutex::new(HashSet::new())),
+            popularity: None,
+        }
+    }
+
+    /// Records conversions and coalesced upstream fetches in the given monitor.
+    pub fn with_monitor(mut self, monitor: Arc<UsageMonitor>) -> Self {
+        self.fetcher.monitor = Some(monitor);
+        self
+    }
+
+    /// Ranks base currencies by their rate lookups, for the refresh scheduler.
+    pub fn with_popularity(mut self, popularity: Arc<BasePopularity>) -> Self {
+        self.popularity = Some(popularity);
+        self
     }
 
+    /// Converts between the currencies of two countries, recording the outcome in the monitor.
     pub async fn convert_currency(
         &self,
         request: &ConversionRequest,
+    ) -> Result<DetailedConversionResponse, ServiceError> {
+        let result = self.convert(request).await;
+        if let Some(monitor) = &self.fetcher.monitor {
+            match &result {
+                Ok(response) => monitor.record_success(response.meta.cache_hit.unwrap_or(false)).await,
+                Err(e) => monitor.record_error(e).await,
+            }
+        }
+        result
+    }
+
+    async fn convert(
+        &self,
+        request: &ConversionRequest,
     ) -> Result<DetailedConversionResponse, ServiceError> {
         let start_time = std::time::Instant::now();
         let request_id = Uuid::new_v4().to_string();
@@ -35,31 +152,46 @@ where
         debug!("Processing conversion request: {:?}", request);
 
         // Get source country details
-        let from_country = self.client.get_country_info(&request.from).await?;
+        let from_country = self.country_client
+            .get_country_info(&format_country_name(&request.from))
+            .await?;
         let from_currencies = Self::get_available_currencies(&from_country);
 
         // Get destination country details
-        let to_country = self.client.get_country_info(&request.to).await?;
+        let to_country = self.country_client
+            .get_country_info(&format_country_name(&request.to))
+            .await?;
         let to_currencies = Self::get_available_currencies(&to_country);
 
+        // The preferred currency only has to be offered by one side
+        let preferred = request.preferred_currency.as_deref();
+        if let Some(preferred) = preferred {
+            if !from_currencies.iter().chain(to_currencies.iter()).any(|c| c.code == preferred) {
+                return Err(ServiceError::InvalidCurrency(format!(
+                    "Preferred currency {} not available", preferred
+                )));
+            }
+        }
+
         // Select appropriate currencies
-        let from_currency = self.select_currency(&from_currencies, request.preferred_currency.as_deref())?;
-        let to_currency = self.select_currency(&to_currencies, request.preferred_currency.as_deref())?;
+        let from_currency = self.select_currency(&from_currencies, preferred)?;
+        let to_currency = self.select_currency(&to_currencies, preferred)?;
 
         // Get exchange rate
-        let (converted_amount, rate, last_updated) = self.get_conversion_rate(
+        let quote = self.get_conversion_rate(
             &from_currency.code,
             &to_currency.code,
             request.amount,
         ).await?;
 
         info!(
-            "Conversion successful: {} {} -> {} {} (rate: {})",
+            "Conversion successful: {} {} -> {} {} (rate: {}, source: {})",
             request.amount,
             from_currency.code,
-            converted_amount,
+            quote.converted_amount,
             to_currency.code,
-            rate
+            quote.rate,
+            quote.source
         );
 
         // Create combined available currencies list if needed
@@ -91,19 +223,23 @@ where
                     currency_code: to_currency.code.clone(),
                     currency_name: to_currency.name.clone(),
                     currency_symbol: to_currency.symbol.clone(),
-                    amount: converted_amount,
+                    amount: quote.converted_amount,
                     is_primary: to_currency.is_primary,
                 },
-                exchange_rate: rate,
-                last_updated,
+                exchange_rate: quote.rate,
+                last_updated: quote.last_updated,
                 available_currencies,
             },
             meta: ResponseMetadata {
-                source: "exchangerate-api.com".to_string(),
+                source: quote.source,
                 response_time_ms: start_time.elapsed().as_millis() as u64,
                 multiple_currencies_available,
-                cache_hit: None,  // TODO implement cache tracking
-                rate_limit_remaining: None,  // TODO implement rate limiting
+                cache_hit: Some(quote.cache_hit),
+                rate_limit_remaining: None,  // Filled in by the handler from the client's rate limit
+                derived_rate: quote.pivot.is_some(),
+                pivot_currency: quote.pivot,
+                stale: quote.stale,
+                rate_age_seconds: quote.age.map(|age| age.num_seconds().max(0) as u64),
             },
         })
     }
@@ -127,17 +263,16 @@ where
         currencies: &'a [AvailableCurrency],
         preferred: Option<&str>,
     ) -> Result<&'a AvailableCurrency, ServiceError> {
-        match (preferred, currencies.len()) {
-            (Some(preferred), _) => currencies
-                .iter()
-                .find(|c| c.code == preferred)
-                .ok_or_else(|| ServiceError::InvalidCurrency(format!("Preferred currency {} not available", preferred))),
-            (None, 1) => Ok(&currencies[0]),
-            (None, _) => currencies
-                .iter()
-                .find(|c| c.is_primary)
-                .ok_or_else(|| ServiceError::InvalidCurrency("No primary currency found".to_string())),
+        if let Some(currency) = preferred.and_then(|p| currencies.iter().find(|c| c.code == p)) {
+            return Ok(currency);
         }
+
+        // Prefer a primary currency, otherwise fall back to a stable choice
+        currencies
+            .iter()
+            .find(|c| c.is_primary)
+            .or_else(|| currencies.iter().min_by(|a, b| a.code.cmp(&b.code)))
+            .ok_or_else(|| ServiceError::InvalidCurrency("No currency found".to_string()))
     }
 
     async fn get_conversion_rate(
@@ -145,44 +280,175 @@ where
         from_currency: &str,
         to_currency: &str,
         amount: f64,
-    ) -> Result<(f64, f64, DateTime<Utc>), ServiceError> {
-        // Check cache first
-        let cache_key = format!("{}_{}", from_currency, to_currency);
-        if let Some(cached) = self.cache.get(&cache_key).await {
-            debug!("Cache hit for {}->{}", from_currency, to_currency);
-            let rate = cached.rate;
-            let converted_amount = (amount * rate * 100.0).round() / 100.0;
-            return Ok((converted_amount, rate, cached.last_updated));
+    ) -> Result<RateQuote, ServiceError> {
+        // Same currency needs no upstream lookup
+        if from_currency == to_currency {
+            return Ok(RateQuote {
+                converted_amount: round_to_cents(amount),
+                rate: 1.0,
+                last_updated: Utc::now(),
+                source: "identity".to_string(),
+                cache_hit: false,
+                pivot: None,
+                stale: false,
+                age: None,
+            });
+        }
+
+        if let Some(popularity) = &self.popularity {
+            popularity.record(from_currency);
+        }
+
+        // Rate tables are cached per base currency, so any pair from a cached base is free
+        let cached = self.cache.lookup(from_currency).await;
+        if let Some(cached) = cached.as_ref().filter(|cached| !cached.is_stale()) {
+            if let Some(rate) = cached.data.rate(to_currency) {
+                debug!("Cache hit for {}->{}", from_currency, to_currency);
+                return Ok(Self::cached_quote(cached, rate, amount));
+            }
+        }
+
+        // Any other cached table quoting both currencies yields the same snapshot's cross rate
+        if let Some(quote) = self.triangulate(from_currency, to_currency, amount, false).await {
+            return Ok(quote);
+        }
+
+        // Serve a stale table right away and revalidate it in the background
+        if let Some(stale) = cached.as_ref().filter(|cached| cached.is_stale()) {
+            if let Some(rate) = stale.data.rate(to_currency) {
+                debug!("Serving stale rate for {}->{} while revalidating", from_currency, to_currency);
+                self.spawn_refresh(from_currency);
+                return Ok(Self::cached_quote(stale, rate, amount));
+            }
         }
 
         // Get fresh rates from API
-        let response = self.client.get_exchange_rate(from_currency).await?;
-        let now = Utc::now();
-
-        let rate = response.conversion_rates
-            .get(to_currency)
-            .ok_or_else(|| {
-                error!("Exchange rate not found for {}->{}", from_currency, to_currency);
-                ServiceError::InvalidCurrency(format!("Exchange rate not found for {}->{}", from_currency, to_currency))
-            })?;
-
-        let converted_amount = (amount * rate * 100.0).round() / 100.0;
-
-        // Cache the result
-        self.cache.set(
-            cache_key,
-            ExchangeRateData {
-                rate: *rate,
-                last_updated: now,
-            },
-        ).await;
-        
+        let table = match self.fetcher.fetch(from_currency, Some(to_currency)).await {
+            Ok(table) => table,
+            Err(e) if Self::is_upstream_failure(&e) => {
+                // An expired rate beats no rate at all, even a triangulated one
+                let stale = cached
+                    .and_then(|stale| stale.data.rate(to_currency).map(|rate| Self::cached_quote(&stale, rate, amount)));
+                let quote = match stale {
+                    Some(quote) => quote,
+                    None => self.triangulate(from_currency, to_currency, amount, true).await.ok_or(e)?,
+                };
+                warn!(
+                    "Serving stale rate for {}->{} after upstream failure",
+                    from_currency, to_currency
+                );
+                return Ok(quote);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let rate = table.rate(to_currency).ok_or_else(|| {
+            error!("Exchange rate not found for {}->{}", from_currency, to_currency);
+            ServiceError::InvalidCurrency(format!("Exchange rate not found for {}->{}", from_currency, to_currency))
+        })?;
+        let quote = Self::quote(&table, rate, amount, false);
+
         debug!(
             "Exchange rate lookup successful: {} {} = {} {} (rate: {})",
-            amount, from_currency, converted_amount, to_currency, rate
+            amount, from_currency, quote.converted_amount, to_currency, rate
         );
-        
-        Ok((converted_amount, *rate, now))
+
+        Ok(quote)
+    }
+
+    /// Fetches a fresh rate table for `base` into the cache, e.g. ahead of its expiry.
+    pub async fn refresh_rates(&self, base: &str) -> Result<(), ServiceError> {
+        self.fetcher.fetch(base, None).await.map(|_| ())
+    }
+
+    /// Errors meaning upstream could not answer, as opposed to answers such as an unknown currency.
+    fn is_upstream_failure(error: &ServiceError) -> bool {
+        matches!(
+            error,
+            ServiceError::ServiceUnavailable(_)
+                | ServiceError::ExternalApiError(_)
+                | ServiceError::RateLimitExceeded
+                | ServiceError::QuotaExhausted(_)
+        )
+    }
+
+    /// Refetches the table for `base` without blocking the caller; one refresh per base at a time.
+    fn spawn_refresh(&self, base: &str) {
+        {
+            let mut refreshing = self.refreshing.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if !refreshing.insert(base.to_string()) {
+                return;
+            }
+        }
+
+        let base = base.to_string();
+        let fetcher = self.fetcher.clone();
+        let refreshing = Arc::clone(&self.refreshing);
+        tokio::spawn(async move {
+            match fetcher.fetch(&base, None).await {
+                Ok(_) => debug!("Revalidated rate table for {}", base),
+                Err(e) => warn!("Background refresh for {} failed: {}", base, e),
+            }
+            refreshing.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&base);
+        });
+    }
+
+    /// Cross rate from a cached table of any other base. The likely pivots come
+    /// first, the target's own table, then USD and EUR, then every other
+    /// cached base in order.
+    async fn triangulate(
+        &self,
+        from_currency: &str,
+        to_currency: &str,
+        amount: f64,
+        allow_stale: bool,
+    ) -> Option<RateQuote> {
+        let mut cached_bases = self.cache.keys().await;
+        cached_bases.sort();
+        let mut pivots: Vec<String> = Vec::new();
+        let likely = [to_currency, "USD", "EUR"].into_iter().map(str::to_string);
+        for pivot in likely.chain(cached_bases) {
+            if pivot != from_currency && !pivots.contains(&pivot) {
+                pivots.push(pivot);
+            }
+        }
+
+        for pivot in pivots {
+            let Some(cached) = self.cache.peek(&pivot).await else { continue };
+            if cached.is_stale() && !allow_stale {
+                continue;
+            }
+            let Some(rate) = cached.data.cross_rate(from_currency, to_currency) else { continue };
+            debug!(
+                "Triangulated {}->{} through cached {} table",
+                from_currency, to_currency, cached.data.base
+            );
+            let mut quote = Self::cached_quote(&cached, rate, amount);
+            quote.pivot = Some(cached.data.base.clone());
+            return Some(quote);
+        }
+        None
+    }
+
+    fn quote(table: &ExchangeRateData, rate: f64, amount: f64, cache_hit: bool) -> RateQuote {
+        RateQuote {
+            converted_amount: round_to_cents(amount * rate),
+            rate,
+            last_updated: table.last_updated,
+            source: table.source.clone(),
+            cache_hit,
+            pivot: None,
+            stale: false,
+            age: None,
+        }
+    }
+
+    fn cached_quote(cached: &CachedValue<ExchangeRateData>, rate: f64, amount: f64) -> RateQuote {
+        RateQuote {
+            stale: cached.is_stale(),
+            age: Some(cached.age),
+            ..Self::quote(&cached.data, rate, amount, true)
+        }
     }
 }
 
@@ -204,6 +470,7 @@ mod tests {
             result: "success".to_string(),
             conversion_rates,
             time_last_update_utc: Some("2024-01-01".to_string()),
+            source: "mock".to_string(),
         }
     }
 
@@ -259,4 +526,239 @@ mod tests {
         assert_eq!(result.data.exchange_rate, 1.0);
         assert_eq!(result.data.to.amount, 100.0);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_rate_table_cached_per_base_currency() {
+        let mock_client = MockClient::new()
+            .with_country_response(create_test_country_info("United States", "USD", "US Dollar", "$"))
+            .with_country_response(create_test_country_info("France", "EUR", "Euro", "€"))
+            .with_country_response(create_test_country_info("Japan", "JPY", "Japanese Yen", "¥"))
+            .with_rate_response(create_mock_exchange_rate_response("USD", &[("EUR", 0.9), ("JPY", 150.0)]));
+
+        let cache = Arc::new(Cache::new(60, 100));
+        let service = CurrencyService::new(mock_client, Arc::clone(&cache));
+
+        let convert = |to: &str| ConversionRequest {
+            from: "United States".to_string(),
+            to: to.to_string(),
+            amount: 10.0,
+            preferred_currency: None,
+        };
+
+        let first = service.convert_currency(&convert("France")).await.unwrap();
+        assert_eq!(first.meta.cache_hit, Some(false));
+
+        // A different target from the same base is served from the cached table
+        let second = service.convert_currency(&convert("Japan")).await.unwrap();
+        assert_eq!(second.meta.cache_hit, Some(true));
+        assert_eq!(second.data.to.amount, 1500.0);
+
+        let table = cache.get("USD").await.unwrap();
+        assert_eq!(table.rates.len(), 3);
+        assert_eq!(table.last_updated.to_rfc3339(), "2024-01-01T00:00:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn test_cross_rate_triangulated_through_cached_table() {
+        // No rate response configured, so any upstream call would fail
+        let mock_client = MockClient::new()
+            .with_country_response(create_test_country_info("Japan", "JPY", "Japanese Yen", "¥"))
+            .with_country_response(create_test_country_info("Brazil", "BRL", "Brazilian Real", "R$"));
+
+        let cache = Arc::new(Cache::new(60, 100));
+        cache.set(
+            "USD".to_string(),
+            ExchangeRateData::from_response("USD", create_mock_exchange_rate_response("USD", &[("JPY", 150.0), ("BRL", 5.0)])),
+        ).await;
+        let service = CurrencyService::new(mock_client, cache);
+
+        let request = ConversionRequest {
+            from: "Japan".to_string(),
+            to: "Brazil".to_string(),
+            amount: 3000.0,
+            preferred_currency: None,
+        };
+
+        let result = service.convert_currency(&request).await.unwrap();
+        assert_eq!(result.data.to.amount, 100.0);
+        assert!(result.meta.derived_rate);
+        assert_eq!(result.meta.pivot_currency.as_deref(), Some("USD"));
+        assert_eq!(result.meta.cache_hit, Some(true));
+        assert!(!result.meta.stale && result.meta.rate_age_seconds.is_some());
+
+        // With upstream down, an expired pivot table is still used and flagged stale
+        let mock_client = MockClient::new()
+            .with_country_response(create_test_country_info("Japan", "JPY", "Japanese Yen", "¥"))
+            .with_country_response(create_test_country_info("Brazil", "BRL", "Brazilian Real", "R$"));
+        let cache = Arc::new(Cache::new(0, 100).with_stale_window(60));
+        cache.set(
+            "EUR".to_string(),
+            ExchangeRateData::from_response("EUR", create_mock_exchange_rate_response("EUR", &[("JPY", 150.0), ("BRL", 5.0)])),
+        ).await;
+        let service = CurrencyService::new(mock_client, cache);
+
+        let result = service.convert_currency(&request).await.unwrap();
+        assert_eq!(result.meta.pivot_currency.as_deref(), Some("EUR"));
+        assert!(result.meta.stale && result.meta.rate_age_seconds.is_some());
+
+        // Any cached base will do, without calling upstream first
+        let country_client = Arc::new(MockClient::new()
+            .with_country_response(create_test_country_info("Japan", "JPY", "Japanese Yen", "¥"))
+            .with_country_response(create_test_country_info("Brazil", "BRL", "Brazilian Real", "R$")));
+        let rate_client = Arc::new(SlowRateClient { calls: Default::default() });
+        let cache = Arc::new(Cache::new(60, 100));
+        cache.set(
+            "GBP".to_string(),
+            ExchangeRateData::from_response("GBP", create_mock_exchange_rate_response("GBP", &[("JPY", 200.0), ("BRL", 6.0)])),
+        ).await;
+        let service = CurrencyService::with_clients(country_client, rate_client.clone(), cache);
+
+        let result = service.convert_currency(&request).await.unwrap();
+        assert_eq!(result.data.to.amount, 90.0);
+        assert_eq!(result.meta.pivot_currency.as_deref(), Some("GBP"));
+        assert_eq!(rate_client.calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    /// Counts upstream calls and answers slowly so concurrent requests overlap.
+    struct SlowRateClient {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl ExchangeRateClient for SlowRateClient {
+        async fn get_exchange_rate(&self, from_currency: &str) -> Result<ExchangeRateResponse, ServiceError> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            Ok(create_mock_exchange_rate_response(from_currency, &[("EUR", 0.9), ("JPY", 150.0)]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_share_one_upstream_call() {
+        let country_client = Arc::new(MockClient::new()
+            .with_country_response(create_test_country_info("United States", "USD", "US Dollar", "$"))
+            .with_country_response(create_test_country_info("France", "EUR", "Euro", "€"))
+            .with_country_response(create_test_country_info("Japan", "JPY", "Japanese Yen", "¥")));
+        let rate_client = Arc::new(SlowRateClient { calls: Default::default() });
+        let monitor = Arc::new(UsageMonitor::new());
+        let service = Arc::new(
+            CurrencyService::with_clients(country_client, rate_client.clone(), Arc::new(Cache::new(60, 100)))
+                .with_monitor(Arc::clone(&monitor)),
+        );
+
+        let handles: Vec<_> = ["France", "Japan", "France", "Japan"]
+            .into_iter()
+            .map(|to| {
+                let service = Arc::clone(&service);
+                tokio::spawn(async move {
+                    service.convert_currency(&ConversionRequest {
+                        from: "United States".to_string(),
+                        to: to.to_string(),
+                        amount: 10.0,
+                        preferred_currency: None,
+                    }).await
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.await.unwrap().is_ok());
+        }
+
+        assert_eq!(rate_client.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        let stats = monitor.get_stats().await;
+        assert_eq!((stats.coalesced_requests, stats.api_calls), (3, 1));
+    }
+
+    #[tokio::test]
+    async fn test_conversions_are_recorded_in_monitor() {
+        let mock_client = MockClient::new()
+            .with_country_response(create_test_country_info("United States", "USD", "US Dollar", "$"))
+            .with_country_response(create_test_country_info("France", "EUR", "Euro", "€"))
+            .with_rate_response(create_mock_exchange_rate_response("USD", &[("EUR", 0.9)]));
+        let monitor = Arc::new(UsageMonitor::new());
+        let service = CurrencyService::new(mock_client, Arc::new(Cache::new(60, 100)))
+            .with_monitor(Arc::clone(&monitor));
+
+        // The identity conversion makes no upstream call
+        for to in ["France", "France", "Atlantis", "United States"] {
+            let _ = service.convert_currency(&ConversionRequest {
+                from: "United States".to_string(),
+                to: to.to_string(),
+                amount: 10.0,
+                preferred_currency: None,
+            }).await;
+        }
+
+        let stats = monitor.get_stats().await;
+        assert_eq!((stats.total_requests, stats.successful_requests, stats.errors), (4, 3, 1));
+        assert_eq!((stats.api_calls, stats.cache_hits), (1, 1));
+        assert_eq!(stats.errors_by_kind["CountryNotFound"], 1);
+
+        monitor.reset_stats().await;
+        assert_eq!(monitor.get_stats().await.total_requests, 0);
+    }
+
+    #[tokio::test]
+    async fn test_serves_stale_rate_when_upstream_unavailable() {
+        let mock_client = MockClient::new()
+            .with_country_response(create_test_country_info("United States", "USD", "US Dollar", "$"))
+            .with_country_response(create_test_country_info("France", "EUR", "Euro", "€"));
+
+        // Zero TTL so the entry is already stale when read
+        let cache = Arc::new(Cache::new(0, 100).with_stale_window(60));
+        cache.set(
+            "USD".to_string(),
+            ExchangeRateData::from_response("USD", create_mock_exchange_rate_response("USD", &[("EUR", 0.9)])),
+        ).await;
+        let service = CurrencyService::new(mock_client, cache);
+
+        let request = ConversionRequest {
+            from: "United States".to_string(),
+            to: "France".to_string(),
+            amount: 10.0,
+            preferred_currency: None,
+        };
+
+        let result = service.convert_currency(&request).await.unwrap();
+        assert_eq!(result.data.exchange_rate, 0.9);
+        assert_eq!(result.data.to.amount, 9.0);
+        assert_eq!(result.meta.cache_hit, Some(true));
+        assert!(result.meta.stale);
+        assert!(result.meta.rate_age_seconds.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_stale_rate_revalidated_in_background() {
+        let mock_client = MockClient::new()
+            .with_country_response(create_test_country_info("United States", "USD", "US Dollar", "$"))
+            .with_country_response(create_test_country_info("France", "EUR", "Euro", "€"))
+            .with_rate_response(create_mock_exchange_rate_response("USD", &[("EUR", 0.8)]));
+
+        let cache = Arc::new(Cache::new(0, 100).with_stale_window(60));
+        cache.set(
+            "USD".to_string(),
+            ExchangeRateData::from_response("USD", create_mock_exchange_rate_response("USD", &[("EUR", 0.9)])),
+        ).await;
+        let service = CurrencyService::new(mock_client, Arc::clone(&cache));
+
+        let request = ConversionRequest {
+            from: "United States".to_string(),
+            to: "France".to_string(),
+            amount: 10.0,
+            preferred_currency: None,
+        };
+
+        // The stale rate is answered immediately while the refresh runs
+        let result = service.convert_currency(&request).await.unwrap();
+        assert_eq!(result.data.exchange_rate, 0.9);
+        assert!(result.meta.stale);
+
+        for _ in 0..50 {
+            if cache.get_stale("USD").await.and_then(|table| table.rate("EUR")) == Some(0.8) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("stale table was not refreshed");
+    }
+}
@@ -3,13 +3,19 @@ use chrono::{DateTime, Utc};
 use serde::Serialize;
 use actix_web::{HttpResponse, ResponseError};
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum ServiceError {
     #[error("Country not found: {0}")]
     CountryNotFound(String),
     
     #[error("Rate limit exceeded")]
     RateLimitExceeded,
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
     
     #[error("External API error: {0}")]
     ExternalApiError(String),
@@ -26,6 +32,10 @@ pub enum ServiceError {
     #[error("Service unavailable: {0}")]
     ServiceUnavailable(String),
 
+    /// The upstream plan does not allow another call; nothing was sent
+    #[error("Upstream quota exhausted: {0}")]
+    QuotaExhausted(String),
+
     #[error("Registry error: {0}")]
     RegistryError(String),
 
@@ -33,6 +43,47 @@ pub enum ServiceError {
     InitializationError(String),
 }
 
+impl ServiceError {
+    /// Variant name, used to count errors by kind.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServiceError::CountryNotFound(_) => "CountryNotFound",
+            ServiceError::RateLimitExceeded => "RateLimitExceeded",
+            ServiceError::Unauthorized(_) => "Unauthorized",
+            ServiceError::Forbidden(_) => "Forbidden",
+            ServiceError::ExternalApiError(_) => "ExternalApiError",
+            ServiceError::CacheError(_) => "CacheError",
+            ServiceError::ConfigError(_) => "ConfigError",
+            ServiceError::InvalidCurrency(_) => "InvalidCurrency",
+            ServiceError::ServiceUnavailable(_) => "ServiceUnavailable",
+            ServiceError::QuotaExhausted(_) => "QuotaExhausted",
+            ServiceError::RegistryError(_) => "RegistryError",
+            ServiceError::InitializationError(_) => "InitializationError",
+        }
+    }
+
+    /// Code sent in the `ErrorResponse` for this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServiceError::RateLimitExceeded => "RATE_LIMIT_EXCEEDED",
+            ServiceError::Unauthorized(_) => "UNAUTHORIZED",
+            ServiceError::Forbidden(_) => "FORBIDDEN",
+            ServiceError::CountryNotFound(_) => "COUNTRY_NOT_FOUND",
+            ServiceError::InvalidCurrency(_) => "INVALID_CURRENCY",
+            ServiceError::ExternalApiError(_)
+            | ServiceError::ServiceUnavailable(_)
+            | ServiceError::QuotaExhausted(_) => "SERVICE_UNAVAILABLE",
+            ServiceError::ConfigError(_) | ServiceError::InitializationError(_) => "CONFIG_ERROR",
+            _ => "INTERNAL_ERROR",
+        }
+    }
+}
+
+/// Error code of a response, kept in its extensions so middleware can count
+/// errors without parsing the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode(pub &'static str);
+
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error: String,
@@ -62,50 +113,68 @@ impl ErrorResponse {
 
 impl ResponseError for ServiceError {
     fn error_response(&self) -> HttpResponse {
-        match self {
+        let mut response = match self {
             ServiceError::RateLimitExceeded => {
                 let error_response = ErrorResponse::new(
                     self.to_string(),
-                    "RATE_LIMIT_EXCEEDED",
+                    self.code(),
                 );
                 HttpResponse::TooManyRequests().json(error_response)
             }
+            ServiceError::Unauthorized(_) => {
+                let error_response = ErrorResponse::new(
+                    self.to_string(),
+                    self.code(),
+                );
+                HttpResponse::Unauthorized().json(error_response)
+            }
+            ServiceError::Forbidden(_) => {
+                let error_response = ErrorResponse::new(
+                    self.to_string(),
+                    self.code(),
+                );
+                HttpResponse::Forbidden().json(error_response)
+            }
             ServiceError::CountryNotFound(_) => {
                 let error_response = ErrorResponse::new(
                     self.to_string(),
-                    "COUNTRY_NOT_FOUND",
+                    self.code(),
                 );
                 HttpResponse::NotFound().json(error_response)
             }
             ServiceError::InvalidCurrency(_) => {
                 let error_response = ErrorResponse::new(
                     self.to_string(),
-                    "INVALID_CURRENCY",
+                    self.code(),
                 );
                 HttpResponse::BadRequest().json(error_response)
             }
-            ServiceError::ExternalApiError(_) | ServiceError::ServiceUnavailable(_) => {
+            ServiceError::ExternalApiError(_)
+            | ServiceError::ServiceUnavailable(_)
+            | ServiceError::QuotaExhausted(_) => {
                 let error_response = ErrorResponse::new(
                     "Service temporarily unavailable",
-                    "SERVICE_UNAVAILABLE",
+                    self.code(),
                 ).with_details(self.to_string());
                 HttpResponse::ServiceUnavailable().json(error_response)
             }
             ServiceError::ConfigError(_) | ServiceError::InitializationError(_) => {
                 let error_response = ErrorResponse::new(
                     "Service configuration error",
-                    "CONFIG_ERROR",
+                    self.code(),
                 ).with_details(self.to_string());
                 HttpResponse::InternalServerError().json(error_response)
             }
             _ => {
                 let error_response = ErrorResponse::new(
                     "Internal server error",
-                    "INTERNAL_ERROR",
+                    self.code(),
                 ).with_details(self.to_string());
                 HttpResponse::InternalServerError().json(error_response)
             }
-        }
+        };
+        response.extensions_mut().insert(ErrorCode(self.code()));
+        response
     }
 }
 
@@ -134,5 +203,6 @@ mod tests {
         let error = ServiceError::CountryNotFound("Test".to_string());
         let response = error.error_response();
         assert_eq!(response.status(), 404);
+        assert_eq!(response.extensions().get::<ErrorCode>(), Some(&ErrorCode("COUNTRY_NOT_FOUND")));
     }
 }
\ No newline at end of file
@@ -2,204 +2,116 @@
 
 use crate::{
     models::{ConversionRequest, SimpleConversionResponse, Validate},
-    errors::ServiceError,
-    clients::{HttpClient, CountryClient, ExchangeRateClient},
+    errors::{ErrorCode, ServiceError},
+    registry::ServiceRegistry,
 };
 use actix_web::{web, HttpResponse, http::header::ContentType};
 use log::{debug, info};
-use reqwest::Client;
-use std::env;
-
-fn round_to_cents(amount: f64) -> f64 {
-    (amount * 100.0).round() / 100.0
-}
-
-fn format_country_name(name: &str) -> String {
-    name.split_whitespace()
-        .map(|word| {
-            let mut chars = word.chars();
-            match chars.next() {
-                None => String::new(),
-                Some(first) => {
-                    let mut word = first.to_uppercase().collect::<String>();
-                    word.extend(chars.map(|c| c.to_lowercase().next().unwrap_or(c)));
-                    word
-                }
-            }
-        })
-        .collect::<Vec<String>>()
-        .join(" ")
-}
 
 pub async fn convert_currency(
     data: web::Json<ConversionRequest>,
-    client: web::Data<Client>,
+    registry: web::Data<ServiceRegistry>,
 ) -> Result<HttpResponse, actix_web::Error> {
     debug!("Processing simple conversion request: {:?}", data);
 
     // Validate request
     if let Err(e) = data.0.validate() {
         debug!("Success: Expected and received validation error - {}", e);
-        return Ok(HttpResponse::BadRequest()
+        let mut response = HttpResponse::BadRequest()
             .content_type(ContentType::json())
             .json(SimpleConversionResponse {
                 from: "ERROR".to_string(),
                 to: "ERROR".to_string(),
                 amount: 0.0,
-            }));
+            });
+        response.extensions_mut().insert(ErrorCode(e.code()));
+        return Ok(response);
     }
 
-    // Check for API key first - before any other operations
-    let api_key = match env::var("EXCHANGE_RATE_API_KEY") {
-        Ok(key) => key,
-        Err(_) => {
-            debug!("Success: Expected API key missing for test case");
-            return Ok(HttpResponse::ServiceUnavailable()
-                .content_type(ContentType::json())
-                .json(SimpleConversionResponse {
-                    from: "ERROR".to_string(),
-                    to: "ERROR".to_string(),
-                    amount: 0.0,
-                }));
-        }
-    };
-
-    let http_client = HttpClient::new(client.get_ref().clone(), api_key);
-    let from_country = format_country_name(&data.from);
-    let to_country = format_country_name(&data.to);
-
-    // Get source country details
-    let (from_currency_code, _) = match get_country_details(&http_client, &from_country).await {
-        Ok(info) => {
-            let first_currency = info.currencies.iter().next().ok_or_else(|| {
-                ServiceError::InvalidCurrency(format!("No currency found for {}", from_country))
-            })?;
-            (first_currency.0.clone(), first_currency.1.clone())
-        }
-        Err(e) => {
-            debug!(
-                "Success: Expected country not found for test case '{}': {}. This may be expected for invalid country tests.", 
-                from_country, e
-            );
-            return Ok(HttpResponse::Ok()
-                .content_type(ContentType::json())
-                .json(SimpleConversionResponse {
-                    from: "INVALID".to_string(),
-                    to: "INVALID".to_string(),
-                    amount: 0.0,
-                }));
-        }
-    };
-
-    // Get destination country details
-    let (to_currency_code, _) = match get_country_details(&http_client, &to_country).await {
-        Ok(info) => {
-            let first_currency = info.currencies.iter().next().ok_or_else(|| {
-                ServiceError::InvalidCurrency(format!("No currency found for {}", to_country))
-            })?;
-            (first_currency.0.clone(), first_currency.1.clone())
-        }
-        Err(e) => {
-            debug!(
-                "Success: Expected country not found for test case '{}': {}. This may be expected for invalid country tests.", 
-                to_country, e
-            );
-            return Ok(HttpResponse::Ok()
-                .content_type(ContentType::json())
-                .json(SimpleConversionResponse {
-                    from: "INVALID".to_string(),
-                    to: "INVALID".to_string(),
-                    amount: 0.0,
-                }));
-        }
-    };
-
-    // If both currencies are the same, return original amount
-    if from_currency_code == to_currency_code {
-        debug!(
-            "Same currency conversion: {} -> {}, returning original amount", 
-            from_currency_code, to_currency_code
-        );
-        return Ok(HttpResponse::Ok()
-            .content_type(ContentType::json())
-            .json(SimpleConversionResponse {
-                from: from_currency_code.clone(),
-                to: to_currency_code,
-                amount: round_to_cents(data.amount),
-            }));
-    }
-
-    // Get exchange rates and perform conversion
-    match get_conversion_details(&http_client, &from_currency_code, &to_currency_code, data.amount).await {
-        Ok((converted_amount, rate, _)) => {
+    match registry.currency_service.convert_currency(&data.0).await {
+        Ok(response) => {
             info!(
                 "Conversion successful: {} {} -> {} {} (rate: {})",
-                data.amount, from_currency_code, converted_amount, to_currency_code, rate
+                data.amount,
+                response.data.from.currency_code,
+                response.data.to.amount,
+                response.data.to.currency_code,
+                response.data.exchange_rate
             );
             Ok(HttpResponse::Ok()
                 .content_type(ContentType::json())
                 .json(SimpleConversionResponse {
-                    from: from_currency_code,
-                    to: to_currency_code,
-                    amount: round_to_cents(converted_amount),
+                    from: response.data.from.currency_code,
+                    to: response.data.to.currency_code,
+                    amount: response.data.to.amount,
                 }))
         }
         Err(e) => {
-            debug!("Success: Expected exchange rate service error - {}", e);
-            Ok(HttpResponse::ServiceUnavailable()
-                .content_type(ContentType::json())
-                .json(SimpleConversionResponse {
-                    from: "ERROR".to_string(),
-                    to: "ERROR".to_string(),
-                    amount: 0.0,
-                }))
+            let mut response = match &e {
+                ServiceError::CountryNotFound(country) => {
+                    debug!(
+                        "Success: Expected country not found for test case '{}'. This may be expected for invalid country tests.",
+                        country
+                    );
+                    HttpResponse::Ok()
+                        .content_type(ContentType::json())
+                        .json(SimpleConversionResponse {
+                            from: "INVALID".to_string(),
+                            to: "INVALID".to_string(),
+                            amount: 0.0,
+                        })
+                }
+                ServiceError::InvalidCurrency(message) => {
+                    debug!("Success: Expected currency selection error - {}", message);
+                    HttpResponse::BadRequest()
+                        .content_type(ContentType::json())
+                        .json(SimpleConversionResponse {
+                            from: "ERROR".to_string(),
+                            to: "ERROR".to_string(),
+                            amount: 0.0,
+                        })
+                }
+                _ => {
+                    debug!("Success: Expected exchange rate service error - {}", e);
+                    HttpResponse::ServiceUnavailable()
+                        .content_type(ContentType::json())
+                        .json(SimpleConversionResponse {
+                            from: "ERROR".to_string(),
+                            to: "ERROR".to_string(),
+                            amount: 0.0,
+                        })
+                }
+            };
+            // The body carries no code, so the request metrics read it from here
+            response.extensions_mut().insert(ErrorCode(e.code()));
+            Ok(response)
         }
     }
 }
 
-async fn get_country_details(client: &HttpClient, country: &str) -> Result<crate::models::CountryInfo, ServiceError> {
-    debug!("Looking up country details for: {}", country);
-    client.get_country_info(country).await
-}
-
-async fn get_conversion_details(
-    client: &HttpClient,
-    from_currency: &str,
-    to_currency: &str,
-    amount: f64,
-) -> Result<(f64, f64, chrono::DateTime<chrono::Utc>), ServiceError> {
-    debug!("Fetching exchange rate for {} -> {}", from_currency, to_currency);
-    
-    let response = client.get_exchange_rate(from_currency).await?;
-    
-    let rate = response.conversion_rates.get(to_currency)
-        .ok_or_else(|| {
-            ServiceError::InvalidCurrency(
-                format!("Exchange rate not found for {}->{}", from_currency, to_currency)
-            )
-        })?;
-
-    let converted_amount = amount * rate;
-    let last_updated = chrono::Utc::now();
-    
-    debug!(
-        "Exchange rate lookup successful: {} {} = {} {} (rate: {})",
-        amount, from_currency, converted_amount, to_currency, rate
-    );
-    
-    Ok((converted_amount, *rate, last_updated))
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        clients::tests::{MockClient, create_test_country_info},
+        config::Config,
+        format_country_name, round_to_cents,
+    };
     use actix_web::test;
 
+    fn test_registry(client: MockClient) -> web::Data<ServiceRegistry> {
+        let config = Config {
+            exchange_rate_api_key: "test_key".to_string(),
+            ..Default::default()
+        };
+        web::Data::new(ServiceRegistry::with_client(&config, client).unwrap())
+    }
+
     #[actix_web::test]
     async fn test_simple_conversion_validation() {
         let app = test::init_service(
             actix_web::App::new()
-                .app_data(web::Data::new(reqwest::Client::new()))
+                .app_data(test_registry(MockClient::new()))
                 .service(web::resource("/currency")
                     .route(web::post().to(convert_currency)))
         ).await;
@@ -224,16 +136,19 @@ mod tests {
     }
 
     #[actix_web::test]
-    async fn test_convert_currency_missing_api_key() {
+    async fn test_convert_currency_upstream_unavailable() {
+        // Countries resolve, but no exchange rate response is configured
+        let client = MockClient::new()
+            .with_country_response(create_test_country_info("Usa", "USD", "US Dollar", "$"))
+            .with_country_response(create_test_country_info("France", "EUR", "Euro", "€"));
+
         let app = test::init_service(
             actix_web::App::new()
-                .app_data(web::Data::new(reqwest::Client::new()))
+                .app_data(test_registry(client))
                 .service(web::resource("/currency")
                     .route(web::post().to(convert_currency)))
         ).await;
 
-        env::remove_var("EXCHANGE_RATE_API_KEY");
-
         let req = test::TestRequest::post()
             .uri("/currency")
             .set_json(ConversionRequest {
@@ -246,6 +161,7 @@ mod tests {
 
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), 503);
+        assert_eq!(resp.response().extensions().get::<ErrorCode>(), Some(&ErrorCode("SERVICE_UNAVAILABLE")));
 
         let body: SimpleConversionResponse = test::read_body_json(resp).await;
         assert_eq!(body.from, "ERROR");
@@ -253,6 +169,47 @@ mod tests {
         assert_eq!(body.amount, 0.0);
     }
 
+    #[actix_web::test]
+    async fn test_convert_currency_through_registry() {
+        let mut rates = std::collections::HashMap::new();
+        rates.insert("USD".to_string(), 1.0);
+        rates.insert("EUR".to_string(), 0.85);
+        let client = MockClient::new()
+            .with_country_response(create_test_country_info("United States", "USD", "US Dollar", "$"))
+            .with_country_response(create_test_country_info("France", "EUR", "Euro", "€"))
+            .with_rate_response(crate::models::ExchangeRateResponse {
+                result: "success".to_string(),
+                conversion_rates: rates,
+                time_last_update_utc: None,
+                source: "mock".to_string(),
+            });
+
+        let app = test::init_service(
+            actix_web::App::new()
+                .app_data(test_registry(client))
+                .service(web::resource("/currency")
+                    .route(web::post().to(convert_currency)))
+        ).await;
+
+        let req = test::TestRequest::post()
+            .uri("/currency")
+            .set_json(ConversionRequest {
+                from: "united states".into(),
+                to: "FRANCE".into(),
+                amount: 100.0,
+                preferred_currency: None,
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let body: SimpleConversionResponse = test::read_body_json(resp).await;
+        assert_eq!(body.from, "USD");
+        assert_eq!(body.to, "EUR");
+        assert_eq!(body.amount, 85.0);
+    }
+
     #[actix_web::test]
     async fn test_format_country_name() {
         assert_eq!(format_country_name("united states"), "United States");
@@ -266,4 +223,4 @@ mod tests {
         assert_eq!(round_to_cents(10.454), 10.45);
         assert_eq!(round_to_cents(10.0), 10.0);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,39 @@
+use crate::errors::ErrorResponse;
+use crate::registry::ServiceRegistry;
+use actix_web::{web, HttpResponse};
+
+/// Read-only counters, size and entry age distribution of every cache.
+pub async fn cache_stats(registry: web::Data<ServiceRegistry>) -> HttpResponse {
+    HttpResponse::Ok().json(registry.monitor.cache_stats().await)
+}
+
+/// Request, error and cache counters since startup or the last reset.
+pub async fn usage_stats(registry: web::Data<ServiceRegistry>) -> HttpResponse {
+    HttpResponse::Ok().json(registry.monitor.get_stats().await)
+}
+
+/// Zeroes the request counters; cache counters belong to the caches and are kept.
+pub async fn reset_usage_stats(registry: web::Data<ServiceRegistry>) -> HttpResponse {
+    registry.monitor.reset_stats().await;
+    HttpResponse::NoContent().finish()
+}
+
+/// Calls left against the exchange rate plan today and this month, and the
+/// mode the quota currently puts the service in.
+pub async fn quota_status(registry: web::Data<ServiceRegistry>) -> HttpResponse {
+    match &registry.quota {
+        Some(quota) => HttpResponse::Ok().json(quota.status().await),
+        None => HttpResponse::NotFound().json(ErrorResponse::new(
+            "Upstream quota tracking is disabled",
+            "QUOTA_DISABLED",
+        )),
+    }
+}
+
+/// Request, upstream, cache and rate limiter counters in the Prometheus text
+/// format, for scraping.
+pub async fn metrics(registry: web::Data<ServiceRegistry>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(crate::metrics::CONTENT_TYPE)
+        .body(crate::metrics::render(&registry.monitor).await)
+}
@@ -1,23 +1,20 @@
 use crate::{
-    models::{
-        ConversionRequest, DetailedConversionResponse, DetailedErrorResponse,
-        ConversionData, CurrencyDetails, ResponseMetadata, Validate
-    },
-    clients::{HttpClient, CountryClient, ExchangeRateClient},
-    errors::ServiceError
+    auth::Tenant,
+    models::{ConversionRequest, DetailedErrorResponse, SimpleConversionResponse, Validate},
+    errors::{ErrorCode, ServiceError},
+    rate_limit::RateLimitStatus,
+    registry::ServiceRegistry,
 };
-use actix_web::{web, HttpResponse, http::header::ContentType};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, http::header::ContentType};
 use log::{debug, info};
-use reqwest::Client;
-use std::env;
 use chrono::Utc;
 use uuid::Uuid;
 
 pub async fn convert_currency(
+    req: HttpRequest,
     data: web::Json<ConversionRequest>,
-    client: web::Data<Client>,
+    registry: web::Data<ServiceRegistry>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let start_time = std::time::Instant::now();
     let request_id = Uuid::new_v4().to_string();
     
     debug!("Processing v1 conversion request: {:?}", data);
@@ -25,7 +22,7 @@ pub async fn convert_currency(
     // Validate request
     if let Err(e) = data.0.validate() {
         debug!("Success: Expected validation error - {}", e);
-        return Ok(HttpResponse::BadRequest()
+        let mut response = HttpResponse::BadRequest()
             .content_type(ContentType::json())
             .json(DetailedErrorResponse {
                 error: e.to_string(),
@@ -33,159 +30,62 @@ pub async fn convert_currency(
                 timestamp: Utc::now(),
                 available_currencies: None,
                 details: None,
-            }));
+            });
+        response.extensions_mut().insert(ErrorCode(e.code()));
+        return Ok(response);
     }
 
-    // Check for API key first
-    let api_key = match env::var("EXCHANGE_RATE_API_KEY") {
-        Ok(key) => key,
-        Err(_) => {
-            debug!("Success: Expected API key missing for test case");
-            return Ok(HttpResponse::ServiceUnavailable()
-                .content_type(ContentType::json())
-                .json(DetailedErrorResponse {
-                    error: "Service configuration error".to_string(),
-                    request_id,
-                    timestamp: Utc::now(),
-                    available_currencies: None,
-                    details: Some("API key not configured".to_string()),
-                }));
-        }
-    };
-
-    let http_client = HttpClient::new(client.get_ref().clone(), api_key);
-    let from_country = format_country_name(&data.from);
-    let to_country = format_country_name(&data.to);
+    match registry.currency_service.convert_currency(&data.0).await {
+        Ok(mut response) => {
+            // Set by the rate limit middleware when the scope is limited
+            response.meta.rate_limit_remaining = req
+                .extensions()
+                .get::<RateLimitStatus>()
+                .map(|status| u32::try_from(status.remaining).unwrap_or(u32::MAX));
+            info!(
+                "Conversion successful: {} {} -> {} {} (rate: {})",
+                data.amount,
+                response.data.from.currency_code,
+                response.data.to.amount,
+                response.data.to.currency_code,
+                response.data.exchange_rate
+            );
 
-    // Get source country details
-    let from_country_info = match http_client.get_country_info(&from_country).await {
-        Ok(info) => info,
-        Err(_) => {
-            debug!("Success: Expected country not found for test case '{}'", from_country);
-            return Ok(HttpResponse::BadRequest()
+            // Tiers without detailed responses get the legacy shape
+            let detailed = req
+                .extensions()
+                .get::<Tenant>()
+                .is_none_or(|tenant| tenant.tier.detailed_responses);
+            if !detailed {
+                return Ok(HttpResponse::Ok()
+                    .content_type(ContentType::json())
+                    .json(SimpleConversionResponse {
+                        from: response.data.from.currency_code,
+                        to: response.data.to.currency_code,
+                        amount: response.data.to.amount,
+                    }));
+            }
+            Ok(HttpResponse::Ok()
                 .content_type(ContentType::json())
-                .json(DetailedErrorResponse {
-                    error: format!("Country not found: {}", from_country),
-                    request_id,
-                    timestamp: Utc::now(),
-                    available_currencies: None,
-                    details: None,
-                }));
+                .json(response))
         }
-    };
-
-    // Get destination country details
-    let to_country_info = match http_client.get_country_info(&to_country).await {
-        Ok(info) => info,
-        Err(_) => {
-            debug!("Success: Expected country not found for test case '{}'", to_country);
-            return Ok(HttpResponse::BadRequest()
+        Err(e @ ServiceError::CountryNotFound(_)) | Err(e @ ServiceError::InvalidCurrency(_)) => {
+            debug!("Success: Expected lookup error for test case - {}", e);
+            let mut response = HttpResponse::BadRequest()
                 .content_type(ContentType::json())
                 .json(DetailedErrorResponse {
-                    error: format!("Country not found: {}", to_country),
+                    error: e.to_string(),
                     request_id,
                     timestamp: Utc::now(),
                     available_currencies: None,
                     details: None,
-                }));
-        }
-    };
-
-    // Get primary currencies
-    let (from_currency, from_currency_info) = from_country_info.currencies.iter().next()
-        .ok_or(ServiceError::InvalidCurrency(format!("No currency found for {}", from_country)))?;
-    
-    let (to_currency, to_currency_info) = to_country_info.currencies.iter().next()
-        .ok_or(ServiceError::InvalidCurrency(format!("No currency found for {}", to_country)))?;
-
-    // If same currency, return original amount
-    if from_currency == to_currency {
-        debug!(
-            "Success: Expected same currency test case {} -> {}, returning original amount", 
-            from_currency, to_currency
-        );
-        return Ok(HttpResponse::Ok()
-            .content_type(ContentType::json())
-            .json(DetailedConversionResponse {
-                request_id,
-                timestamp: Utc::now(),
-                data: ConversionData {
-                    from: CurrencyDetails {
-                        country: from_country_info.name.common,
-                        currency_code: from_currency.clone(),
-                        currency_name: from_currency_info.name.clone(),
-                        currency_symbol: from_currency_info.symbol.clone(),
-                        amount: data.amount,
-                        is_primary: true,
-                    },
-                    to: CurrencyDetails {
-                        country: to_country_info.name.common,
-                        currency_code: to_currency.clone(),
-                        currency_name: to_currency_info.name.clone(),
-                        currency_symbol: to_currency_info.symbol.clone(),
-                        amount: data.amount,
-                        is_primary: true,
-                    },
-                    exchange_rate: 1.0,
-                    last_updated: Utc::now(),
-                    available_currencies: None,
-                },
-                meta: ResponseMetadata {
-                    source: "exchangerate-api.com".to_string(),
-                    response_time_ms: start_time.elapsed().as_millis() as u64,
-                    multiple_currencies_available: false,
-                    cache_hit: None,
-                    rate_limit_remaining: None,
-                },
-            }));
-    }
-
-    // Get exchange rates and perform conversion
-    match get_conversion_details(&http_client, from_currency, to_currency, data.amount).await {
-        Ok((converted_amount, rate, last_updated)) => {
-            info!(
-                "Conversion successful: {} {} -> {} {} (rate: {})",
-                data.amount, from_currency, converted_amount, to_currency, rate
-            );
-            
-            Ok(HttpResponse::Ok()
-                .content_type(ContentType::json())
-                .json(DetailedConversionResponse {
-                    request_id,
-                    timestamp: Utc::now(),
-                    data: ConversionData {
-                        from: CurrencyDetails {
-                            country: from_country_info.name.common,
-                            currency_code: from_currency.clone(),
-                            currency_name: from_currency_info.name.clone(),
-                            currency_symbol: from_currency_info.symbol.clone(),
-                            amount: data.amount,
-                            is_primary: true,
-                        },
-                        to: CurrencyDetails {
-                            country: to_country_info.name.common,
-                            currency_code: to_currency.clone(),
-                            currency_name: to_currency_info.name.clone(),
-                            currency_symbol: to_currency_info.symbol.clone(),
-                            amount: round_to_cents(converted_amount),
-                            is_primary: true,
-                        },
-                        exchange_rate: rate,
-                        last_updated,
-                        available_currencies: None,
-                    },
-                    meta: ResponseMetadata {
-                        source: "exchangerate-api.com".to_string(),
-                        response_time_ms: start_time.elapsed().as_millis() as u64,
-                        multiple_currencies_available: false,
-                        cache_hit: None,
-                        rate_limit_remaining: None,
-                    },
-                }))
+                });
+            response.extensions_mut().insert(ErrorCode(e.code()));
+            Ok(response)
         }
         Err(e) => {
             debug!("Success: Expected exchange rate service error - {}", e);
-            Ok(HttpResponse::ServiceUnavailable()
+            let mut response = HttpResponse::ServiceUnavailable()
                 .content_type(ContentType::json())
                 .json(DetailedErrorResponse {
                     error: "Service temporarily unavailable".to_string(),
@@ -193,70 +93,37 @@ pub async fn convert_currency(
                     timestamp: Utc::now(),
                     available_currencies: None,
                     details: Some(e.to_string()),
-                }))
+                });
+            response.extensions_mut().insert(ErrorCode(e.code()));
+            Ok(response)
         }
     }
 }
 
-async fn get_conversion_details(
-    client: &HttpClient,
-    from_currency: &str,
-    to_currency: &str,
-    amount: f64,
-) -> Result<(f64, f64, chrono::DateTime<chrono::Utc>), ServiceError> {
-    debug!("Fetching exchange rate for {} -> {}", from_currency, to_currency);
-    
-    let response = client.get_exchange_rate(from_currency).await?;
-    
-    let rate = response.conversion_rates.get(to_currency)
-        .ok_or_else(|| {
-            ServiceError::InvalidCurrency(
-                format!("Exchange rate not found for {}->{}", from_currency, to_currency)
-            )
-        })?;
-
-    let converted_amount = amount * rate;
-    let last_updated = chrono::Utc::now();
-    
-    debug!(
-        "Exchange rate lookup successful: {} {} = {} {} (rate: {})",
-        amount, from_currency, converted_amount, to_currency, rate
-    );
-    
-    Ok((converted_amount, *rate, last_updated))
-}
-
-fn round_to_cents(amount: f64) -> f64 {
-    (amount * 100.0).round() / 100.0
-}
-
-fn format_country_name(name: &str) -> String {
-    name.split_whitespace()
-        .map(|word| {
-            let mut chars = word.chars();
-            match chars.next() {
-                None => String::new(),
-                Some(first) => {
-                    let mut word = first.to_uppercase().collect::<String>();
-                    word.extend(chars.map(|c| c.to_lowercase().next().unwrap_or(c)));
-                    word
-                }
-            }
-        })
-        .collect::<Vec<String>>()
-        .join(" ")
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        clients::tests::{MockClient, create_test_country_info},
+        config::Config,
+        models::DetailedConversionResponse,
+        format_country_name, round_to_cents,
+    };
     use actix_web::test;
 
+    fn test_registry(client: MockClient) -> web::Data<ServiceRegistry> {
+        let config = Config {
+            exchange_rate_api_key: "test_key".to_string(),
+            ..Default::default()
+        };
+        web::Data::new(ServiceRegistry::with_client(&config, client).unwrap())
+    }
+
     #[actix_web::test]
     async fn test_simple_conversion_validation() {
         let app = test::init_service(
             actix_web::App::new()
-                .app_data(web::Data::new(reqwest::Client::new()))
+                .app_data(test_registry(MockClient::new()))
                 .service(web::resource("/currency")
                     .route(web::post().to(convert_currency)))
         ).await;
@@ -280,16 +147,19 @@ mod tests {
     }
 
     #[actix_web::test]
-    async fn test_convert_currency_missing_api_key() {
+    async fn test_convert_currency_upstream_unavailable() {
+        // Countries resolve, but no exchange rate response is configured
+        let client = MockClient::new()
+            .with_country_response(create_test_country_info("Usa", "USD", "US Dollar", "$"))
+            .with_country_response(create_test_country_info("France", "EUR", "Euro", "€"));
+
         let app = test::init_service(
             actix_web::App::new()
-                .app_data(web::Data::new(reqwest::Client::new()))
+                .app_data(test_registry(client))
                 .service(web::resource("/currency")
                     .route(web::post().to(convert_currency)))
         ).await;
 
-        env::remove_var("EXCHANGE_RATE_API_KEY");
-
         let req = test::TestRequest::post()
             .uri("/currency")
             .set_json(ConversionRequest {
@@ -304,10 +174,59 @@ mod tests {
         assert_eq!(resp.status(), 503);
 
         let body: DetailedErrorResponse = test::read_body_json(resp).await;
-        assert!(body.error.contains("Service configuration error"));
+        assert!(body.error.contains("Service temporarily unavailable"));
         assert!(!body.request_id.is_empty());
     }
 
+    #[actix_web::test]
+    async fn test_convert_currency_uses_cache_and_preferred_currency() {
+        let mut rates = std::collections::HashMap::new();
+        rates.insert("EUR".to_string(), 1.0);
+        rates.insert("USD".to_string(), 1.1);
+        let mut panama = create_test_country_info("Panama", "PAB", "Panamanian Balboa", "B/.");
+        panama.currencies.insert(
+            "USD".to_string(),
+            crate::models::CurrencyInfo { name: "US Dollar".to_string(), symbol: "$".to_string() },
+        );
+        let client = MockClient::new()
+            .with_country_response(create_test_country_info("France", "EUR", "Euro", "€"))
+            .with_country_response(panama)
+            .with_rate_response(crate::models::ExchangeRateResponse {
+                result: "success".to_string(),
+                conversion_rates: rates,
+                time_last_update_utc: None,
+                source: "mock".to_string(),
+            });
+
+        let app = test::init_service(
+            actix_web::App::new()
+                .app_data(test_registry(client))
+                .service(web::resource("/currency")
+                    .route(web::post().to(convert_currency)))
+        ).await;
+
+        for expected_cache_hit in [false, true] {
+            let req = test::TestRequest::post()
+                .uri("/currency")
+                .set_json(ConversionRequest {
+                    from: "France".into(),
+                    to: "Panama".into(),
+                    amount: 100.0,
+                    preferred_currency: Some("USD".into()),
+                })
+                .to_request();
+
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let body: DetailedConversionResponse = test::read_body_json(resp).await;
+            assert_eq!(body.data.from.currency_code, "EUR");
+            assert_eq!(body.data.to.currency_code, "USD");
+            assert_eq!(body.data.to.amount, 110.0);
+            assert_eq!(body.meta.cache_hit, Some(expected_cache_hit));
+        }
+    }
+
     #[test]
     async fn test_format_country_name() {
         assert_eq!(format_country_name("united states"), "United States");
@@ -321,4 +240,4 @@ mod tests {
         assert_eq!(round_to_cents(10.454), 10.45);
         assert_eq!(round_to_cents(10.0), 10.0);
     }
-}
\ No newline at end of file
+}
@@ -1,14 +1,42 @@
 pub async fn health_check() -> actix_web::Result<&'static str> {
     Ok("OK")
 }
+
+/// Reports overall health along with the state of every upstream circuit breaker.
+pub async fn health_status(
+    registry: actix_web::web::Data<registry::ServiceRegistry>,
+) -> actix_web::HttpResponse {
+    use clients::circuit_breaker::CircuitState;
+
+    let circuit_breakers = registry.circuit_breakers.statuses();
+    let status = if circuit_breakers.values().all(|b| b.state == CircuitState::Closed) {
+        "OK"
+    } else {
+        "DEGRADED"
+    };
+
+    actix_web::HttpResponse::Ok().json(models::HealthResponse {
+        status: status.to_string(),
+        circuit_breakers,
+    })
+}
+pub mod app;
+pub mod auth;
 pub mod registry;
 pub mod handlers;
 pub mod handlers_v1;
+pub mod handlers_admin;
 pub mod models;
 pub mod cache;
+pub mod cache_backend;
+pub mod single_flight;
 pub mod config;
+pub mod metrics;
+pub mod middleware;
 pub mod monitor;
+pub mod persistence;
 pub mod rate_limit;
+pub mod refresh;
 pub mod currency_service;
 pub mod errors;
 pub mod clients;
@@ -1,15 +1,15 @@
-use actix_web::{web, App, HttpServer};
+use actix_web::{web, HttpServer};
 use currency_converter::{
-    handlers, handlers_v1,
+    app::build_app,
     cache::{Cache, ExchangeRateData},
+    models::CountryInfo,
     config::Config,
+    refresh::RefreshScheduler,
     registry::ServiceRegistry,
 };
 use dotenv::dotenv;
 use log::{info, error, debug};
-use std::{io, time::Duration};
-use currency_converter::health_check;
-use reqwest::Client;
+use std::{io, sync::Arc, time::Duration};
 
 #[actix_web::main]
 async fn main() -> io::Result<()> {
@@ -20,33 +20,22 @@ async fn main() -> io::Result<()> {
     // Load configuration
     let config = Config::new().map_err(|e| {
         error!("Failed to load configuration: {}", e);
-        io::Error::new(io::ErrorKind::Other, e)
+        io::Error::other(e)
     })?;
 
-    // Initialize HTTP client
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30))
-        .build()
-        .expect("Failed to create HTTP client");
-    let client_data = web::Data::new(client);
-    
     // Initialize service registry
     let registry = ServiceRegistry::new(&config).map_err(|e| {
         error!("Failed to initialize services: {}", e);
-        io::Error::new(io::ErrorKind::Other, e)
+        io::Error::other(e)
     })?;
     let registry = web::Data::new(registry);
-    
-    // Initialize caches
-    let exchange_rate_cache = web::Data::new(ExchangeRateData::new_cache());
-    let country_cache = web::Data::new(Cache::<String>::new(
-        24 * 60, // 24 hours TTL
-        500      // Maximum number of country entries to cache
-    ));
+
+    // Warm caches from the previous run
+    registry.restore_snapshots().await;
 
     // Start cache cleanup task
-    let cleanup_exchange_rate_cache = exchange_rate_cache.clone();
-    let cleanup_country_cache = country_cache.clone();
+    let cleanup_exchange_rate_cache = Arc::clone(&registry.cache);
+    let cleanup_country_cache = Arc::clone(&registry.country_cache);
     tokio::spawn(async move {
         let cleanup_interval = Duration::from_secs(300); // 5 minutes
         start_cache_cleanup(
@@ -56,53 +45,52 @@ async fn main() -> io::Result<()> {
         ).await;
     });
 
+    // Keep popular rate tables warm
+    if config.refresh_settings.enabled {
+        let scheduler = RefreshScheduler::new(
+            Arc::clone(&registry.currency_service),
+            Arc::clone(&registry.cache),
+            Arc::clone(&registry.popularity),
+            &config.refresh_settings,
+            &config.rate_limit_settings,
+        )
+        .with_quota(registry.quota.clone());
+        let check_interval = Duration::from_secs(config.refresh_settings.check_interval_seconds.max(1));
+        tokio::spawn(scheduler.run(check_interval));
+    }
+
+    // Start cache snapshot task
+    if config.persistence_settings.snapshot_dir.is_some() {
+        let snapshot_registry = registry.clone();
+        let snapshot_interval = Duration::from_secs(config.persistence_settings.snapshot_interval_minutes.max(1) * 60);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(snapshot_interval).await;
+                debug!("Writing periodic cache snapshots");
+                snapshot_registry.save_snapshots().await;
+            }
+        });
+    }
+
     info!("Starting currency converter service at http://localhost:8080");
     
     // Start HTTP server
-    HttpServer::new(move || {
-        App::new()
-            // Add shared HTTP client
-            .app_data(client_data.clone())
-            // Add registry
-            .app_data(registry.clone())
-            // Add shared services
-            .app_data(exchange_rate_cache.clone())
-            .app_data(country_cache.clone())
-            
-            // Health check endpoint
-            .service(
-                web::resource("/health")
-                    .route(web::get().to(health_check))
-            )
-            
-            // API v1 routes
-            .service(
-                web::scope("/v1")
-                    .configure(configure_v1_routes)
-            )
-            
-            // Legacy routes (without version prefix)
-            .service(
-                web::resource("/currency")
-                    .route(web::post().to(handlers::convert_currency))
-            )
-    })
+    let server_registry = registry.clone();
+    let client_limits = config.client_rate_limit_settings.clone();
+    HttpServer::new(move || build_app(server_registry.clone(), &client_limits))
     .bind("127.0.0.1:8080")?
     .workers(4)
     .run()
-    .await
-}
+    .await?;
 
-fn configure_v1_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("/currency")
-            .route("", web::post().to(handlers_v1::convert_currency))
-    );
+    info!("Server stopped, running cleanup");
+    registry.cleanup().await;
+    Ok(())
 }
 
 async fn start_cache_cleanup(
-    exchange_rate_cache: web::Data<Cache<ExchangeRateData>>,
-    country_cache: web::Data<Cache<String>>,
+    exchange_rate_cache: Arc<Cache<ExchangeRateData>>,
+    country_cache: Arc<Cache<CountryInfo>>,
     cleanup_interval: Duration
 ) {
     loop {
@@ -116,7 +104,7 @@ async fn start_cache_cleanup(
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use currency_converter::health_check;
 
     #[actix_web::test]
     async fn test_health_check() {
@@ -0,0 +1,150 @@
+use crate::cache::CacheStats;
+use crate::monitor::{LatencyHistogram, UsageMonitor, LATENCY_BUCKETS};
+use std::fmt::{Display, Write};
+
+/// Content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const PREFIX: &str = "currency_converter_";
+
+/// Name, help text and value of a per-cache counter.
+type CacheCounter = (&'static str, &'static str, fn(&CacheStats) -> u64);
+
+/// Escapes a label value as the exposition format requires.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[derive(Default)]
+struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {PREFIX}{name} {help}");
+        let _ = writeln!(self.out, "# TYPE {PREFIX}{name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let _ = write!(self.out, "{PREFIX}{name}");
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+                .collect();
+            let _ = write!(self.out, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.out, " {value}");
+    }
+
+    /// Writes the cumulative `_bucket` series, then `_sum` and `_count`.
+    fn histogram(&mut self, name: &str, label: (&str, &str), histogram: &LatencyHistogram) {
+        let bucket = format!("{name}_bucket");
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            self.sample(&bucket, &[label, ("le", &bound.to_string())], cumulative);
+        }
+        self.sample(&bucket, &[label, ("le", "+Inf")], histogram.count);
+        self.sample(&format!("{name}_sum"), &[label], histogram.sum_seconds);
+        self.sample(&format!("{name}_count"), &[label], histogram.count);
+    }
+}
+
+/// Renders the monitor's HTTP, upstream, cache and rate limiter counters in
+/// the Prometheus text format. Every series is prefixed `currency_converter_`.
+pub async fn render(monitor: &UsageMonitor) -> String {
+    let http = monitor.http_metrics().await;
+    let caches = monitor.cache_counters().await;
+    let mut exposition = Exposition::default();
+
+    exposition.family("http_requests_total", "counter", "HTTP responses by route, status and error code.");
+    for (outcome, count) in &http.requests {
+        let status = outcome.status.to_string();
+        let labels = [("route", outcome.route.as_str()), ("status", status.as_str()), ("code", outcome.code.as_str())];
+        exposition.sample("http_requests_total", &labels, count);
+    }
+
+    exposition.family("http_request_duration_seconds", "histogram", "Time taken to answer HTTP requests, by route.");
+    for (route, histogram) in &http.latency {
+        exposition.histogram("http_request_duration_seconds", ("route", route), histogram);
+    }
+
+    exposition.family("upstream_requests_total", "counter", "Calls to upstream providers by outcome.");
+    for (provider, histogram) in &http.upstream_latency {
+        let failures = http.upstream_failures.get(provider).copied().unwrap_or_default();
+        let successes = histogram.count - failures;
+        exposition.sample("upstream_requests_total", &[("provider", provider), ("outcome", "success")], successes);
+        exposition.sample("upstream_requests_total", &[("provider", provider), ("outcome", "failure")], failures);
+    }
+
+    exposition.family("upstream_request_duration_seconds", "histogram", "Time taken by upstream provider calls.");
+    for (provider, histogram) in &http.upstream_latency {
+        exposition.histogram("upstream_request_duration_seconds", ("provider", provider), histogram);
+    }
+
+    let cache_counters: [CacheCounter; 5] = [
+        ("cache_hits_total", "Lookups answered with a fresh entry.", |stats| stats.hits),
+        ("cache_stale_hits_total", "Lookups answered with a stale entry.", |stats| stats.stale_hits),
+        ("cache_misses_total", "Lookups that found no usable entry.", |stats| stats.misses),
+        ("cache_evictions_total", "Entries evicted to make room.", |stats| stats.evictions),
+        ("cache_expirations_total", "Entries dropped after their stale window ran out.", |stats| stats.expirations),
+    ];
+    for (name, help, counter) in cache_counters {
+        exposition.family(name, "counter", help);
+        for (cache, stats) in &caches {
+            exposition.sample(name, &[("cache", cache)], counter(stats));
+        }
+    }
+
+    exposition.family("cache_entries", "gauge", "Entries currently stored, stale ones included.");
+    for (cache, stats) in &caches {
+        exposition.sample("cache_entries", &[("cache", cache)], stats.size);
+    }
+
+    exposition.family("rate_limit_rejections_total", "counter", "Requests refused by a client rate limiter.");
+    for (limiter, rejections) in monitor.rate_limit_rejections() {
+        exposition.sample("rate_limit_rejections_total", &[("limiter", &limiter)], rejections);
+    }
+
+    exposition.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::RateLimiter;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_render_exposition() {
+        let monitor = UsageMonitor::new();
+        monitor.record_http_request("/v1/currency", 200, None, Duration::from_millis(20)).await;
+        monitor.record_http_request("/v1/currency", 400, Some("INVALID_CURRENCY"), Duration::from_millis(3)).await;
+        monitor.record_provider_attempt("exchangerate-api", false, Duration::from_secs(2)).await;
+
+        let limiter = Arc::new(RateLimiter::new(1));
+        limiter.check("client").await;
+        limiter.check("client").await;
+        monitor.register_rate_limiter("/v1", limiter);
+
+        let text = render(&monitor).await;
+        assert!(text.contains("# TYPE currency_converter_http_requests_total counter\n"));
+        assert!(text.contains(
+            "currency_converter_http_requests_total{route=\"/v1/currency\",status=\"400\",code=\"INVALID_CURRENCY\"} 1\n"
+        ));
+        // Buckets are cumulative and end with +Inf
+        assert!(text.contains("currency_converter_http_request_duration_seconds_bucket{route=\"/v1/currency\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("currency_converter_http_request_duration_seconds_bucket{route=\"/v1/currency\",le=\"0.025\"} 2\n"));
+        assert!(text.contains("currency_converter_http_request_duration_seconds_bucket{route=\"/v1/currency\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("currency_converter_http_request_duration_seconds_count{route=\"/v1/currency\"} 2\n"));
+        assert!(text.contains(
+            "currency_converter_upstream_requests_total{provider=\"exchangerate-api\",outcome=\"failure\"} 1\n"
+        ));
+        assert!(text.contains("currency_converter_rate_limit_rejections_total{limiter=\"/v1\"} 1\n"));
+
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -0,0 +1,436 @@
+use crate::auth::{KeyStore, Tenant};
+use crate::config::ClientRateLimitSettings;
+use crate::errors::{ErrorCode, ServiceError};
+use crate::monitor::UsageMonitor;
+use crate::rate_limit::{RateLimitStatus, RateLimiter};
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    Error, HttpMessage, ResponseError,
+};
+use log::debug;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Header carrying a client's API key.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Rate limit headers from the IETF `RateLimit` header fields draft.
+pub const RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+fn api_key(req: &ServiceRequest) -> Option<&str> {
+    req.headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+}
+
+/// Identifies the client a request counts against: its tenant once
+/// `Authenticate` has validated the API key, otherwise its IP address.
+///
+/// An unvalidated key is ignored, so sending random keys cannot buy a client
+/// fresh allowances.
+pub fn client_key(req: &ServiceRequest, trust_forwarded_for: bool) -> String {
+    if let Some(tenant) = req.extensions().get::<Tenant>() {
+        return format!("tenant:{}", tenant.id);
+    }
+
+    let info = req.connection_info();
+    let ip = if trust_forwarded_for {
+        info.realip_remote_addr()
+    } else {
+        info.peer_addr()
+    };
+    format!("ip:{}", ip.unwrap_or("unknown"))
+}
+
+/// Adds the `RateLimit-*` headers, and `Retry-After` when the request was refused.
+fn insert_rate_limit_headers(headers: &mut HeaderMap, status: &RateLimitStatus) {
+    // Round up so clients never retry a moment too early
+    let reset = status.reset_after.as_secs() + u64::from(status.reset_after.subsec_nanos() > 0);
+    headers.insert(RATE_LIMIT_LIMIT, HeaderValue::from(status.limit));
+    headers.insert(RATE_LIMIT_REMAINING, HeaderValue::from(status.remaining));
+    headers.insert(RATE_LIMIT_RESET, HeaderValue::from(reset));
+    if !status.allowed {
+        headers.insert(RETRY_AFTER, HeaderValue::from(reset));
+    }
+}
+
+/// Rejects requests without a known API key with 401, and keys whose tier
+/// does not include the scope with 403.
+///
+/// Wrap each route scope with its own instance, outside `RateLimit` so tier
+/// limits apply; without a key store every request is let through. Handlers
+/// can read the `Tenant` from the request extensions.
+#[derive(Clone)]
+pub struct Authenticate {
+    store: Option<Arc<dyn KeyStore>>,
+    scope: Arc<str>,
+}
+
+impl Authenticate {
+    pub fn new(store: Option<Arc<dyn KeyStore>>, scope: &str) -> Self {
+        Self {
+            store,
+            scope: Arc::from(scope),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Authenticate
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AuthenticateMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticateMiddleware {
+            service: Rc::new(service),
+            store: self.store.clone(),
+            scope: Arc::clone(&self.scope),
+        }))
+    }
+}
+
+pub struct AuthenticateMiddleware<S> {
+    service: Rc<S>,
+    store: Option<Arc<dyn KeyStore>>,
+    scope: Arc<str>,
+}
+
+impl<S> AuthenticateMiddleware<S> {
+    async fn tenant(store: &dyn KeyStore, api_key: Option<&str>, scope: &str) -> Result<Tenant, ServiceError> {
+        let api_key = api_key.ok_or_else(|| ServiceError::Unauthorized(format!("{} header required", API_KEY_HEADER)))?;
+        let tenant = store
+            .tenant(api_key)
+            .await?
+            .ok_or_else(|| ServiceError::Unauthorized("Invalid API key".to_string()))?;
+        if !tenant.tier.allows(scope) {
+            return Err(ServiceError::Forbidden(format!(
+                "The {} tier does not include {}", tenant.tier.name, scope
+            )));
+        }
+        Ok(tenant)
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticateMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let Some(store) = self.store.clone() else {
+            return Box::pin(async move { service.call(req).await.map(ServiceResponse::map_into_left_body) });
+        };
+        let scope = Arc::clone(&self.scope);
+
+        Box::pin(async move {
+            let api_key = api_key(&req).map(str::to_string);
+            match Self::tenant(store.as_ref(), api_key.as_deref(), &scope).await {
+                Ok(tenant) => {
+                    req.extensions_mut().insert(tenant);
+                    service.call(req).await.map(ServiceResponse::map_into_left_body)
+                }
+                Err(e) => {
+                    debug!("Rejected request to {}: {}", scope, e);
+                    Ok(req.into_response(e.error_response()).map_into_right_body())
+                }
+            }
+        })
+    }
+}
+
+/// Rejects requests with 429 once their client has used up the scope's limit.
+///
+/// Wrap each route scope with its own limiter, e.g. `/currency` and `/v1`;
+/// without a limiter the middleware lets everything through. Authenticated
+/// tenants whose tier has its own limits are held to those instead, across
+/// scopes. Responses carry `RateLimit-*` headers, and handlers can read the
+/// `RateLimitStatus` from the request extensions.
+#[derive(Clone)]
+pub struct RateLimit {
+    limiter: Option<Arc<RateLimiter>>,
+    trust_forwarded_for: bool,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Option<Arc<RateLimiter>>, settings: &ClientRateLimitSettings) -> Self {
+        Self {
+            limiter,
+            trust_forwarded_for: settings.trust_forwarded_for,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limiter: self.limiter.clone(),
+            trust_forwarded_for: self.trust_forwarded_for,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Option<Arc<RateLimiter>>,
+    trust_forwarded_for: bool,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let tier_limit = req.extensions().get::<Tenant>().and_then(|tenant| {
+            let limiter = tenant.tier.limiter.clone()?;
+            Some((limiter, format!("tenant:{}", tenant.id)))
+        });
+        let (limiter, key) = match (tier_limit, self.limiter.clone()) {
+            (Some(tier_limit), _) => tier_limit,
+            (None, Some(limiter)) => (limiter, client_key(&req, self.trust_forwarded_for)),
+            (None, None) => {
+                return Box::pin(async move { service.call(req).await.map(ServiceResponse::map_into_left_body) })
+            }
+        };
+
+        Box::pin(async move {
+            let status = limiter.check(&key).await;
+            if !status.allowed {
+                let mut response = ServiceError::RateLimitExceeded.error_response();
+                insert_rate_limit_headers(response.headers_mut(), &status);
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            req.extensions_mut().insert(status);
+            let mut response = service.call(req).await?;
+            insert_rate_limit_headers(response.headers_mut(), &status);
+            Ok(response.map_into_left_body())
+        })
+    }
+}
+
+/// Counts every response by route, status and error code, and times it per
+/// route, for the `/metrics` endpoint.
+///
+/// Wrap the whole app with it so rejections by `Authenticate` and `RateLimit`
+/// are counted too. Requests matching no route are counted as `unmatched`.
+#[derive(Clone)]
+pub struct RecordMetrics {
+    monitor: Arc<UsageMonitor>,
+}
+
+impl RecordMetrics {
+    pub fn new(monitor: Arc<UsageMonitor>) -> Self {
+        Self { monitor }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RecordMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RecordMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RecordMetricsMiddleware {
+            service: Rc::new(service),
+            monitor: Arc::clone(&self.monitor),
+        }))
+    }
+}
+
+pub struct RecordMetricsMiddleware<S> {
+    service: Rc<S>,
+    monitor: Arc<UsageMonitor>,
+}
+
+impl<S, B> Service<ServiceRequest> for RecordMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let monitor = Arc::clone(&self.monitor);
+        // The pattern rather than the path keeps the number of series bounded
+        let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+        let started = Instant::now();
+
+        Box::pin(async move {
+            let result = service.call(req).await;
+            let (status, code) = match &result {
+                Ok(response) => (
+                    response.status(),
+                    response.response().extensions().get::<ErrorCode>().map(|code| code.0),
+                ),
+                Err(e) => (e.as_response_error().status_code(), None),
+            };
+            monitor
+                .record_http_request(&route, status.as_u16(), code, started.elapsed())
+                .await;
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{FileKeyStore, Tier};
+    use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+
+    #[actix_web::test]
+    async fn test_rate_limit_per_client_and_scope() {
+        let settings = ClientRateLimitSettings::default();
+        let app = test::init_service(
+            App::new()
+                .service(
+                    web::scope("/limited")
+                        .wrap(RateLimit::new(Some(Arc::new(RateLimiter::new(2))), &settings))
+                        .route("", web::get().to(HttpResponse::Ok)),
+                )
+                .service(
+                    web::scope("/open")
+                        .wrap(RateLimit::new(None, &settings))
+                        .route("", web::get().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+
+        let tenant = Tenant {
+            id: "acme".to_string(),
+            tier: Arc::new(Tier {
+                name: "basic".to_string(),
+                endpoints: vec!["/limited".to_string()],
+                detailed_responses: false,
+                limiter: None,
+            }),
+        };
+        let call = |uri: &'static str, api_key: Option<&'static str>, tenant: Option<Tenant>| {
+            let mut req = test::TestRequest::get()
+                .uri(uri)
+                .peer_addr("10.0.0.1:4000".parse().unwrap());
+            if let Some(api_key) = api_key {
+                req = req.insert_header((API_KEY_HEADER, api_key));
+            }
+            let req = req.to_request();
+            if let Some(tenant) = tenant {
+                req.extensions_mut().insert(tenant);
+            }
+            test::call_service(&app, req)
+        };
+
+        for remaining in ["1", "0"] {
+            let resp = call("/limited", None, None).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers().get(RATE_LIMIT_LIMIT).unwrap(), "2");
+            assert_eq!(resp.headers().get(RATE_LIMIT_REMAINING).unwrap(), remaining);
+            assert!(!resp.headers().contains_key(RETRY_AFTER));
+        }
+        let resp = call("/limited", None, None).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(RATE_LIMIT_REMAINING).unwrap(), "0");
+        assert_eq!(resp.headers().get(RETRY_AFTER).unwrap(), resp.headers().get(RATE_LIMIT_RESET).unwrap());
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "RATE_LIMIT_EXCEEDED");
+
+        // An unvalidated key shares its IP's allowance, an authenticated tenant
+        // gets its own, and unlimited scopes are untouched
+        assert_eq!(call("/limited", Some("random-key"), None).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(call("/limited", Some("acme-key"), Some(tenant)).await.status(), StatusCode::OK);
+        assert_eq!(call("/open", None, None).await.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_authenticate_applies_tenant_tier() {
+        let store: Arc<dyn KeyStore> = Arc::new(
+            FileKeyStore::from_json(
+                r#"{
+                    "tiers": { "basic": { "rate_limits": ["1/1d"], "endpoints": ["/currency"] } },
+                    "keys": { "basic-key": { "tenant": "acme", "tier": "basic" } }
+                }"#,
+            )
+            .unwrap(),
+        );
+        let settings = ClientRateLimitSettings::default();
+        let scope = |name: &'static str| {
+            web::scope(name)
+                .wrap(RateLimit::new(Some(Arc::new(RateLimiter::new(100))), &settings))
+                .wrap(Authenticate::new(Some(Arc::clone(&store)), name))
+                .route("", web::get().to(HttpResponse::Ok))
+        };
+        let app = test::init_service(App::new().service(scope("/currency")).service(scope("/v1"))).await;
+
+        let call = |uri: &'static str, api_key: Option<&'static str>| {
+            let mut req = test::TestRequest::get().uri(uri);
+            if let Some(api_key) = api_key {
+                req = req.insert_header((API_KEY_HEADER, api_key));
+            }
+            test::call_service(&app, req.to_request())
+        };
+
+        for api_key in [None, Some("wrong-key")] {
+            let resp = call("/currency", api_key).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["code"], "UNAUTHORIZED");
+        }
+        assert_eq!(call("/v1", Some("basic-key")).await.status(), StatusCode::FORBIDDEN);
+
+        // The tier's own limit replaces the scope's
+        let resp = call("/currency", Some("basic-key")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(RATE_LIMIT_LIMIT).unwrap(), "1");
+        assert_eq!(call("/currency", Some("basic-key")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
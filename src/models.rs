@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use crate::clients::circuit_breaker::CircuitBreakerStatus;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ConversionRequest {
@@ -80,6 +81,17 @@ pub struct ResponseMetadata {
     pub rate_limit_remaining: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cache_hit: Option<bool>,
+    /// True when the rate was triangulated through another base currency's table
+    #[serde(default)]
+    pub derived_rate: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pivot_currency: Option<String>,
+    /// True when the rate is past its TTL, served while revalidating or during an upstream outage
+    #[serde(default)]
+    pub stale: bool,
+    /// Seconds since the rate table was fetched, for cached rates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_age_seconds: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -98,6 +110,33 @@ pub struct ExchangeRateResponse {
     pub result: String,
     pub conversion_rates: HashMap<String, f64>,
     pub time_last_update_utc: Option<String>,
+    /// Provider that served the rates, filled in by the client rather than upstream
+    #[serde(skip)]
+    pub source: String,
+}
+
+impl ExchangeRateResponse {
+    /// Parses `time_last_update_utc`, which providers report either as an
+    /// RFC 2822 timestamp or, like the ECB, as a plain date.
+    pub fn last_updated(&self) -> Option<DateTime<Utc>> {
+        let time = self.time_last_update_utc.as_deref()?.trim();
+        DateTime::parse_from_rfc2822(time)
+            .or_else(|_| DateTime::parse_from_rfc3339(time))
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                chrono::NaiveDate::parse_from_str(time, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|dt| dt.and_utc())
+            })
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HealthResponse {
+    pub status: String,
+    pub circuit_breakers: BTreeMap<String, CircuitBreakerStatus>,
 }
 
 // New validation traits
@@ -160,10 +199,15 @@ mod tests {
             multiple_currencies_available: false,
             rate_limit_remaining: Some(100),
             cache_hit: Some(true),
+            derived_rate: false,
+            pivot_currency: None,
+            stale: false,
+            rate_age_seconds: None,
         };
         let serialized = serde_json::to_string(&metadata).unwrap();
         assert!(serialized.contains("rate_limit_remaining"));
         assert!(serialized.contains("cache_hit"));
+        assert!(!serialized.contains("pivot_currency"));
     }
 
     #[test]
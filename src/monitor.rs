@@ -1,7 +1,217 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use chrono::{DateTime, Utc};
 use serde::Serialize;
+use crate::cache::{CacheStats, CacheStatsSource};
+use crate::errors::ServiceError;
+use crate::rate_limit::RateLimiter;
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Latencies counted into `LATENCY_BUCKETS`; slower ones only show in `count`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyHistogram {
+    /// Observations per bucket, not cumulative
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+    pub sum_seconds: f64,
+    pub count: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; LATENCY_BUCKETS.len()],
+            sum_seconds: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum_seconds += seconds;
+        self.count += 1;
+    }
+}
+
+/// Upstream dependency name for country lookups.
+pub const COUNTRIES_DEPENDENCY: &str = "countries";
+/// Upstream dependency name for exchange rate fetches.
+pub const RATES_DEPENDENCY: &str = "rates";
+
+/// Sliding windows, by name and length in seconds, over which rolling
+/// latency percentiles are reported.
+pub const LATENCY_WINDOWS: [(&str, i64); 3] = [("1m", 60), ("5m", 300), ("1h", 3600)];
+
+/// Rolling latencies are kept in slots of this many seconds, so windows
+/// slide in steps of this size.
+const SLOT_SECONDS: i64 = 10;
+
+/// Each rolling histogram bucket is this much wider than the one before,
+/// which bounds the error of the reported percentiles to 5%.
+const BUCKET_GROWTH: f64 = 1.05;
+
+fn bucket_of(millis: f64) -> i32 {
+    (millis.max(0.001).ln() / BUCKET_GROWTH.ln()).ceil() as i32
+}
+
+fn bucket_upper_bound(bucket: i32) -> f64 {
+    BUCKET_GROWTH.powi(bucket)
+}
+
+/// Latencies observed during one slot.
+#[derive(Debug, Clone)]
+struct LatencySlot {
+    slot: i64,
+    buckets: BTreeMap<i32, u64>,
+    count: u64,
+    max_ms: f64,
+}
+
+/// Percentiles of the latencies seen within one window, in milliseconds.
+#[derive(Debug, Default, Serialize, Clone, PartialEq)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Latencies of the last hour, bucketed on a log scale so memory stays
+/// bounded however busy the service is.
+#[derive(Debug, Default, Clone)]
+pub struct RollingLatency {
+    slots: VecDeque<LatencySlot>,
+}
+
+impl RollingLatency {
+    pub fn observe(&mut self, elapsed: Duration) {
+        self.observe_at(elapsed, Utc::now());
+    }
+
+    fn observe_at(&mut self, elapsed: Duration, now: DateTime<Utc>) {
+        let slot = now.timestamp().div_euclid(SLOT_SECONDS);
+        self.prune(slot);
+        if self.slots.back().is_none_or(|last| last.slot != slot) {
+            self.slots.push_back(LatencySlot {
+                slot,
+                buckets: BTreeMap::new(),
+                count: 0,
+                max_ms: 0.0,
+            });
+        }
+
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        if let Some(last) = self.slots.back_mut() {
+            *last.buckets.entry(bucket_of(millis)).or_default() += 1;
+            last.count += 1;
+            last.max_ms = last.max_ms.max(millis);
+        }
+    }
+
+    /// Drops slots that have fallen out of the longest window.
+    fn prune(&mut self, slot: i64) {
+        let longest = LATENCY_WINDOWS.iter().map(|(_, seconds)| *seconds).max().unwrap_or_default();
+        let oldest = slot - longest / SLOT_SECONDS + 1;
+        while self.slots.front().is_some_and(|first| first.slot < oldest) {
+            self.slots.pop_front();
+        }
+    }
+
+    /// Percentiles over each of the `LATENCY_WINDOWS`, keyed by window name.
+    pub fn summaries(&self) -> BTreeMap<String, LatencySummary> {
+        self.summaries_at(Utc::now())
+    }
+
+    fn summaries_at(&self, now: DateTime<Utc>) -> BTreeMap<String, LatencySummary> {
+        let current = now.timestamp().div_euclid(SLOT_SECONDS);
+        LATENCY_WINDOWS
+            .iter()
+            .map(|(name, seconds)| {
+                let oldest = current - seconds / SLOT_SECONDS + 1;
+                let slots = self.slots.iter().filter(|slot| (oldest..=current).contains(&slot.slot));
+                (name.to_string(), Self::summarize(slots))
+            })
+            .collect()
+    }
+
+    fn summarize<'a>(slots: impl Iterator<Item = &'a LatencySlot>) -> LatencySummary {
+        let mut buckets: BTreeMap<i32, u64> = BTreeMap::new();
+        let mut summary = LatencySummary::default();
+        for slot in slots {
+            for (bucket, count) in &slot.buckets {
+                *buckets.entry(*bucket).or_default() += count;
+            }
+            summary.count += slot.count;
+            summary.max_ms = summary.max_ms.max(slot.max_ms);
+        }
+
+        // The upper bound of the bucket holding the rank, never above the max
+        let percentile = |quantile: f64| {
+            let rank = ((quantile * summary.count as f64).ceil() as u64).max(1);
+            let mut seen = 0;
+            for (bucket, count) in &buckets {
+                seen += count;
+                if seen >= rank {
+                    return bucket_upper_bound(*bucket).min(summary.max_ms);
+                }
+            }
+            summary.max_ms
+        };
+        if summary.count > 0 {
+            summary.p50_ms = percentile(0.5);
+            summary.p90_ms = percentile(0.9);
+            summary.p99_ms = percentile(0.99);
+        }
+        summary
+    }
+}
+
+/// Rolling latency percentiles per window name, e.g. `"5m"`.
+pub type WindowedLatency = BTreeMap<String, LatencySummary>;
+
+/// Where time goes: whole requests per route, and calls to each upstream
+/// dependency on a cache miss.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct LatencyStats {
+    pub endpoints: BTreeMap<String, WindowedLatency>,
+    pub upstream: BTreeMap<String, WindowedLatency>,
+}
+
+#[derive(Debug, Default)]
+struct RollingLatencies {
+    endpoints: BTreeMap<String, RollingLatency>,
+    upstream: BTreeMap<String, RollingLatency>,
+}
+
+/// One kind of HTTP response, as counted per route.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestOutcome {
+    /// Route pattern such as `/v1/currency`, never the raw path
+    pub route: String,
+    pub status: u16,
+    /// `ErrorResponse` code, empty for successful responses
+    pub code: String,
+}
+
+/// Counters that only ever grow, for scraping; `reset_stats` leaves them alone.
+#[derive(Debug, Clone, Default)]
+pub struct HttpMetrics {
+    pub requests: BTreeMap<RequestOutcome, u64>,
+    pub latency: BTreeMap<String, LatencyHistogram>,
+    /// Time spent per upstream call, by provider
+    pub upstream_latency: BTreeMap<String, LatencyHistogram>,
+    /// Upstream calls that failed, by provider
+    pub upstream_failures: BTreeMap<String, u64>,
+}
 
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct UsageStats {
@@ -10,11 +220,38 @@ pub struct UsageStats {
     pub cache_hits: usize,
     pub api_calls: usize,
     pub errors: usize,
+    /// Failed conversions by `ServiceError` variant
+    pub errors_by_kind: BTreeMap<String, usize>,
+    /// Requests that waited on another request's in-flight upstream fetch
+    pub coalesced_requests: usize,
+    pub providers: HashMap<String, ProviderStats>,
+    /// Counters of every registered cache, read when the stats are taken
+    pub caches: BTreeMap<String, CacheStats>,
+    /// Rolling latency percentiles, which age out on their own and survive resets
+    pub latency: LatencyStats,
     pub last_reset: DateTime<Utc>,
 }
 
+#[derive(Debug, Default, Serialize, Clone, PartialEq)]
+pub struct ProviderStats {
+    pub attempts: usize,
+    pub successes: usize,
+    pub failures: usize,
+}
+
 pub struct UsageMonitor {
     stats: Arc<RwLock<UsageStats>>,
+    http: RwLock<HttpMetrics>,
+    latency: RwLock<RollingLatencies>,
+    // Registered while wiring the service, outside any async context
+    caches: std::sync::RwLock<BTreeMap<String, Arc<dyn CacheStatsSource>>>,
+    rate_limiters: std::sync::RwLock<BTreeMap<String, Arc<RateLimiter>>>,
+}
+
+impl Default for UsageMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl UsageMonitor {
@@ -24,7 +261,66 @@ impl UsageMonitor {
                 last_reset: Utc::now(),
                 ..Default::default()
             })),
+            http: RwLock::new(HttpMetrics::default()),
+            latency: RwLock::new(RollingLatencies::default()),
+            caches: std::sync::RwLock::new(BTreeMap::new()),
+            rate_limiters: std::sync::RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Includes a limiter's rejections in the metrics under `name`.
+    pub fn register_rate_limiter(&self, name: &str, limiter: Arc<RateLimiter>) {
+        let mut limiters = self.rate_limiters.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        limiters.insert(name.to_string(), limiter);
+    }
+
+    /// Requests refused so far by each registered limiter.
+    pub fn rate_limit_rejections(&self) -> BTreeMap<String, u64> {
+        self.rate_limiters
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .map(|(name, limiter)| (name.clone(), limiter.rejections()))
+            .collect()
+    }
+
+    /// Includes a cache's counters in the stats under `name`.
+    pub fn register_cache(&self, name: &str, cache: Arc<dyn CacheStatsSource>) {
+        let mut caches = self.caches.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        caches.insert(name.to_string(), cache);
+    }
+
+    pub async fn cache_stats(&self) -> BTreeMap<String, CacheStats> {
+        let caches: Vec<(String, Arc<dyn CacheStatsSource>)> = self
+            .caches
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .map(|(name, cache)| (name.clone(), Arc::clone(cache)))
+            .collect();
+
+        let mut stats = BTreeMap::new();
+        for (name, cache) in caches {
+            stats.insert(name, cache.stats().await);
+        }
+        stats
+    }
+
+    /// Like `cache_stats`, without listing every entry; for frequent scrapes.
+    pub async fn cache_counters(&self) -> BTreeMap<String, CacheStats> {
+        let caches: Vec<(String, Arc<dyn CacheStatsSource>)> = self
+            .caches
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .map(|(name, cache)| (name.clone(), Arc::clone(cache)))
+            .collect();
+
+        let mut stats = BTreeMap::new();
+        for (name, cache) in caches {
+            stats.insert(name, cache.counters().await);
         }
+        stats
     }
 
     pub async fn record_request(&self, cached: bool) {
@@ -32,19 +328,104 @@ impl UsageMonitor {
         stats.total_requests += 1;
         if cached {
             stats.cache_hits += 1;
-        } else {
-            stats.api_calls += 1;
         }
     }
 
-    pub async fn record_error(&self) {
+    /// Counts a conversion that was answered. Upstream calls are counted
+    /// where they are made, since identity conversions and requests that
+    /// joined another's fetch make none.
+    pub async fn record_success(&self, cached: bool) {
+        let mut stats = self.stats.write().await;
+        stats.total_requests += 1;
+        stats.successful_requests += 1;
+        if cached {
+            stats.cache_hits += 1;
+        }
+    }
+
+    /// Counts a rate table fetch sent upstream, whether or not it succeeds.
+    pub async fn record_api_call(&self) {
         let mut stats = self.stats.write().await;
+        stats.api_calls += 1;
+    }
+
+    /// Counts a conversion that failed.
+    pub async fn record_error(&self, error: &ServiceError) {
+        let mut stats = self.stats.write().await;
+        stats.total_requests += 1;
         stats.errors += 1;
+        *stats.errors_by_kind.entry(error.kind().to_string()).or_default() += 1;
+    }
+
+    pub async fn record_coalesced_wait(&self) {
+        let mut stats = self.stats.write().await;
+        stats.coalesced_requests += 1;
+    }
+
+    pub async fn record_http_request(&self, route: &str, status: u16, code: Option<&str>, elapsed: Duration) {
+        {
+            let mut http = self.http.write().await;
+            let outcome = RequestOutcome {
+                route: route.to_string(),
+                status,
+                code: code.unwrap_or_default().to_string(),
+            };
+            *http.requests.entry(outcome).or_default() += 1;
+            http.latency.entry(route.to_string()).or_default().observe(elapsed);
+        }
+
+        let mut latency = self.latency.write().await;
+        latency.endpoints.entry(route.to_string()).or_default().observe(elapsed);
+    }
+
+    /// Times a call to an upstream dependency such as `COUNTRIES_DEPENDENCY`.
+    pub async fn record_upstream_latency(&self, dependency: &str, elapsed: Duration) {
+        let mut latency = self.latency.write().await;
+        latency.upstream.entry(dependency.to_string()).or_default().observe(elapsed);
+    }
+
+    pub async fn latency_stats(&self) -> LatencyStats {
+        let latency = self.latency.read().await;
+        let summarize = |latencies: &BTreeMap<String, RollingLatency>| {
+            latencies
+                .iter()
+                .map(|(name, rolling)| (name.clone(), rolling.summaries()))
+                .collect()
+        };
+        LatencyStats {
+            endpoints: summarize(&latency.endpoints),
+            upstream: summarize(&latency.upstream),
+        }
+    }
+
+    pub async fn http_metrics(&self) -> HttpMetrics {
+        self.http.read().await.clone()
+    }
+
+    pub async fn record_provider_attempt(&self, provider: &str, success: bool, elapsed: Duration) {
+        {
+            let mut http = self.http.write().await;
+            http.upstream_latency.entry(provider.to_string()).or_default().observe(elapsed);
+            if !success {
+                *http.upstream_failures.entry(provider.to_string()).or_default() += 1;
+            }
+        }
+
+        let mut stats = self.stats.write().await;
+        let provider_stats = stats.providers.entry(provider.to_string()).or_default();
+        provider_stats.attempts += 1;
+        if success {
+            provider_stats.successes += 1;
+        } else {
+            provider_stats.failures += 1;
+        }
     }
 
     pub async fn get_stats(&self) -> UsageStats {
-        let stats = self.stats.read().await;
-        (*stats).clone()
+        let mut stats = self.stats.read().await.clone();
+        stats.caches = self.cache_stats().await;
+        stats.latency = self.latency_stats().await;
+        stats
     }
 
     pub async fn reset_stats(&self) {
@@ -54,4 +435,48 @@ impl UsageMonitor {
             ..Default::default()
         };
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rolling_latency_windows() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let mut rolling = RollingLatency::default();
+
+        // A slow hour ago burst, then 100 requests of 1..=100ms in the last minute
+        for _ in 0..10 {
+            rolling.observe_at(Duration::from_secs(3), start);
+        }
+        let recent = start + chrono::Duration::minutes(59);
+        for millis in 1..=100 {
+            rolling.observe_at(Duration::from_millis(millis), recent);
+        }
+
+        let summaries = rolling.summaries_at(recent);
+        let last_minute = &summaries["1m"];
+        assert_eq!((last_minute.count, last_minute.max_ms), (100, 100.0));
+        // Percentiles are accurate to the bucket width
+        for (reported, exact) in [(last_minute.p50_ms, 50.0), (last_minute.p90_ms, 90.0), (last_minute.p99_ms, 99.0)] {
+            assert!(reported >= exact && reported <= exact * BUCKET_GROWTH, "{} vs {}", reported, exact);
+        }
+        assert_eq!(summaries["5m"].count, 100);
+        assert_eq!((summaries["1h"].count, summaries["1h"].max_ms), (110, 3000.0));
+        assert!(summaries["1h"].p99_ms > 2000.0);
+
+        // Once the burst is over an hour old it drops out of every window
+        let later = start + chrono::Duration::minutes(61);
+        rolling.observe_at(Duration::from_millis(5), later);
+        assert_eq!(rolling.summaries_at(later)["1h"].count, 101);
+        assert_eq!(rolling.summaries_at(later)["1m"], LatencySummary {
+            count: 1,
+            p50_ms: 5.0,
+            p90_ms: 5.0,
+            p99_ms: 5.0,
+            max_ms: 5.0,
+        });
+    }
+}
@@ -0,0 +1,166 @@
+use crate::cache::{Cache, SnapshotEntry};
+use crate::errors::ServiceError;
+use log::{debug, info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// On-disk layout of cache snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnapshotFormat {
+    /// A single JSON array
+    #[default]
+    Json,
+    /// One JSON entry per line, so a truncated file still loads up to the damage
+    JsonLines,
+}
+
+impl SnapshotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::JsonLines => "jsonl",
+        }
+    }
+
+    fn encode<T: Serialize>(&self, entries: &[SnapshotEntry<T>]) -> Result<Vec<u8>, ServiceError> {
+        let encoded = match self {
+            Self::Json => serde_json::to_vec(entries).map_err(snapshot_error)?,
+            Self::JsonLines => {
+                let mut buffer = Vec::new();
+                for entry in entries {
+                    serde_json::to_writer(&mut buffer, entry).map_err(snapshot_error)?;
+                    buffer.push(b'\n');
+                }
+                buffer
+            }
+        };
+        Ok(encoded)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, contents: &str) -> Result<Vec<SnapshotEntry<T>>, ServiceError> {
+        match self {
+            Self::Json => serde_json::from_str(contents).map_err(snapshot_error),
+            Self::JsonLines => Ok(contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(|line| match serde_json::from_str(line) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        warn!("Skipping unreadable snapshot line: {}", e);
+                        None
+                    }
+                })
+                .collect()),
+        }
+    }
+}
+
+impl FromStr for SnapshotFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "jsonl" | "json-lines" => Ok(Self::JsonLines),
+            other => Err(format!("Unknown cache snapshot format: {}", other)),
+        }
+    }
+}
+
+fn snapshot_error(e: impl std::fmt::Display) -> ServiceError {
+    ServiceError::CacheError(format!("Cache snapshot failed: {}", e))
+}
+
+/// Saves caches to, and warms them from, snapshot files in one directory.
+#[derive(Debug, Clone)]
+pub struct CacheSnapshots {
+    dir: PathBuf,
+    format: SnapshotFormat,
+}
+
+impl CacheSnapshots {
+    pub fn new(dir: impl Into<PathBuf>, format: SnapshotFormat) -> Self {
+        Self {
+            dir: dir.into(),
+            format,
+        }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, self.format.extension()))
+    }
+
+    /// Writes the cache to `<dir>/<name>.<ext>`, replacing the previous snapshot atomically.
+    pub async fn save<T>(&self, cache: &Cache<T>, name: &str) -> Result<usize, ServiceError>
+    where
+        T: Clone + Serialize + Send + Sync + 'static,
+    {
+        let entries = cache.snapshot().await;
+        let encoded = self.format.encode(&entries)?;
+
+        let path = self.path(name);
+        let temp = path.with_extension("tmp");
+        tokio::fs::create_dir_all(&self.dir).await.map_err(snapshot_error)?;
+        tokio::fs::write(&temp, encoded).await.map_err(snapshot_error)?;
+        tokio::fs::rename(&temp, &path).await.map_err(snapshot_error)?;
+
+        debug!("Saved {} {} cache entries to {}", entries.len(), name, path.display());
+        Ok(entries.len())
+    }
+
+    /// Warms the cache from its snapshot, if one exists. Expired entries are discarded.
+    pub async fn load<T>(&self, cache: &Cache<T>, name: &str) -> Result<usize, ServiceError>
+    where
+        T: Clone + DeserializeOwned + Send + Sync + 'static,
+    {
+        let path = self.path(name);
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(snapshot_error(e)),
+        };
+
+        let restored = cache.restore(self.format.decode(&contents)?).await;
+        info!("Restored {} {} cache entries from {}", restored, name, path.display());
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let dir = std::env::temp_dir().join(format!("cache-snapshots-{}", uuid::Uuid::new_v4()));
+
+        for format in [SnapshotFormat::Json, SnapshotFormat::JsonLines] {
+            let snapshots = CacheSnapshots::new(&dir, format);
+            let cache = Cache::new(60, 10);
+            cache.set("USD".to_string(), 1.5).await;
+            cache.set("EUR".to_string(), 2.5).await;
+            assert_eq!(snapshots.save(&cache, "rates").await.unwrap(), 2);
+
+            let warmed = Cache::<f64>::new(60, 10);
+            assert_eq!(snapshots.load(&warmed, "rates").await.unwrap(), 2);
+            assert_eq!(warmed.get("EUR").await, Some(2.5));
+
+            // Entries that expired since the snapshot was written are discarded
+            let hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+            let expired = vec![SnapshotEntry {
+                key: "GBP".to_string(),
+                data: 0.8,
+                inserted_at: hour_ago,
+                expires_at: hour_ago,
+                stale_until: hour_ago,
+            }];
+            tokio::fs::write(snapshots.path("expired"), format.encode(&expired).unwrap()).await.unwrap();
+            assert_eq!(snapshots.load(&Cache::<f64>::new(60, 10), "expired").await.unwrap(), 0);
+        }
+
+        let missing = CacheSnapshots::new(dir.join("missing"), SnapshotFormat::Json);
+        assert_eq!(missing.load(&Cache::<f64>::new(60, 10), "rates").await.unwrap(), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,89 +1,402 @@
 use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use chrono::{DateTime, Duration, Utc};
 use log::{debug, warn};
+use serde::Deserialize;
 
+/// One limit a client must stay within. Several rules stack, e.g. a burst
+/// limit per second on top of a daily allowance.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum LimitRule {
+    /// Holds up to `burst` requests, refilled continuously at `refill_per_second`
+    TokenBucket { burst: usize, refill_per_second: f64 },
+    /// At most `limit` requests in any trailing `window`
+    SlidingWindow { limit: usize, window: Duration },
+}
+
+impl LimitRule {
+    pub fn per_window(limit: usize, window: Duration) -> Self {
+        Self::SlidingWindow { limit, window }
+    }
+
+    pub fn per_day(limit: usize) -> Self {
+        Self::per_window(limit, Duration::days(1))
+    }
+
+    fn limit(&self) -> usize {
+        match self {
+            Self::TokenBucket { burst, .. } => *burst,
+            Self::SlidingWindow { limit, .. } => *limit,
+        }
+    }
+
+    fn new_state(&self, now: DateTime<Utc>) -> RuleState {
+        match self {
+            Self::TokenBucket { burst, .. } => RuleState::Bucket {
+                tokens: *burst as f64,
+                updated: now,
+            },
+            Self::SlidingWindow { .. } => RuleState::Window {
+                started: now,
+                current: 0,
+                previous: 0,
+            },
+        }
+    }
+}
+
+/// Parses `<limit>/<window>` for a sliding window, e.g. `1000/1d` or `10/s`,
+/// and `bucket:<burst>@<rate>/<unit>` for a token bucket, e.g. `bucket:20@5/s`.
+impl FromStr for LimitRule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid rate limit rule: {}", value);
+        let value = value.trim();
+        let (amount, per) = value.split_once('/').ok_or_else(invalid)?;
+        let period = parse_period(per).ok_or_else(invalid)?;
+
+        match amount.strip_prefix("bucket:") {
+            Some(bucket) => {
+                let (burst, rate) = bucket.split_once('@').ok_or_else(invalid)?;
+                let burst: usize = burst.trim().parse().map_err(|_| invalid())?;
+                let rate: f64 = rate.trim().parse().map_err(|_| invalid())?;
+                let seconds = period.num_milliseconds() as f64 / 1000.0;
+                if rate <= 0.0 {
+                    return Err(invalid());
+                }
+                Ok(Self::TokenBucket {
+                    burst,
+                    refill_per_second: rate / seconds,
+                })
+            }
+            None => Ok(Self::per_window(amount.trim().parse().map_err(|_| invalid())?, period)),
+        }
+    }
+}
+
+impl TryFrom<String> for LimitRule {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// `s`, `m`, `h` or `d`, optionally preceded by a count such as `15m`.
+fn parse_period(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let count = match &value[..value.len() - unit.len_utf8()] {
+        "" => 1,
+        count => count.parse().ok()?,
+    };
+    let period = match unit {
+        's' => Duration::seconds(count),
+        'm' => Duration::minutes(count),
+        'h' => Duration::hours(count),
+        'd' => Duration::days(count),
+        _ => return None,
+    };
+    (period > Duration::zero()).then_some(period)
+}
+
+/// Per-client state for one rule. Both shapes are constant size, so memory
+/// does not grow with the number of requests.
 #[derive(Debug, Clone)]
-pub struct RateLimitInfo {
-    pub requests: Vec<DateTime<Utc>>,
-    pub daily_count: usize,
-    pub last_reset: DateTime<Utc>,
+enum RuleState {
+    Bucket {
+        tokens: f64,
+        updated: DateTime<Utc>,
+    },
+    /// Sliding window counter: the previous window's count is weighted by how
+    /// much of it still overlaps the trailing window
+    Window {
+        started: DateTime<Utc>,
+        current: usize,
+        previous: usize,
+    },
+}
+
+/// What one rule would make of a request, before anything is consumed.
+struct RuleDecision {
+    allowed: bool,
+    remaining: usize,
+    reset_after: Duration,
+}
+
+impl RuleState {
+    /// Brings the state forward to `now`.
+    fn advance(&mut self, rule: &LimitRule, now: DateTime<Utc>) {
+        match (self, rule) {
+            (Self::Bucket { tokens, updated }, LimitRule::TokenBucket { burst, refill_per_second }) => {
+                let elapsed = (now - *updated).num_milliseconds().max(0) as f64 / 1000.0;
+                *tokens = (*tokens + elapsed * refill_per_second).min(*burst as f64);
+                *updated = now;
+            }
+            (Self::Window { started, current, previous }, LimitRule::SlidingWindow { window, .. }) => {
+                let windows_passed = (now - *started).num_milliseconds() / window.num_milliseconds().max(1);
+                if windows_passed >= 1 {
+                    *previous = if windows_passed == 1 { *current } else { 0 };
+                    *current = 0;
+                    *started += *window * windows_passed as i32;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn decide(&self, rule: &LimitRule, now: DateTime<Utc>) -> RuleDecision {
+        match (self, rule) {
+            (Self::Bucket { tokens, .. }, LimitRule::TokenBucket { burst, refill_per_second }) => {
+                let allowed = *tokens >= 1.0;
+                let after = if allowed { tokens - 1.0 } else { *tokens };
+                // Until the next token when refused, until the bucket is full otherwise
+                let missing = if allowed { *burst as f64 - after } else { 1.0 - after };
+                RuleDecision {
+                    allowed,
+                    remaining: after.floor() as usize,
+                    reset_after: Duration::milliseconds((missing / refill_per_second * 1000.0).ceil() as i64),
+                }
+            }
+            (Self::Window { started, current, previous }, LimitRule::SlidingWindow { limit, window }) => {
+                let elapsed = (now - *started).num_milliseconds() as f64 / window.num_milliseconds() as f64;
+                let estimate = *previous as f64 * (1.0 - elapsed).max(0.0) + *current as f64;
+                let allowed = estimate + 1.0 <= *limit as f64;
+                let used = if allowed { estimate + 1.0 } else { estimate };
+                RuleDecision {
+                    allowed,
+                    remaining: (*limit as f64 - used).max(0.0).floor() as usize,
+                    reset_after: (*started + *window - now).max(Duration::zero()),
+                }
+            }
+            _ => RuleDecision {
+                allowed: true,
+                remaining: rule.limit(),
+                reset_after: Duration::zero(),
+            },
+        }
+    }
+
+    fn consume(&mut self) {
+        match self {
+            Self::Bucket { tokens, .. } => *tokens -= 1.0,
+            Self::Window { current, .. } => *current += 1,
+        }
+    }
+
+    /// True once the state is back to what a new client would get.
+    fn is_idle(&self, rule: &LimitRule, now: DateTime<Utc>) -> bool {
+        match (self, rule) {
+            (Self::Bucket { tokens, .. }, LimitRule::TokenBucket { burst, .. }) => *tokens >= *burst as f64,
+            (Self::Window { started, .. }, LimitRule::SlidingWindow { window, .. }) => now - *started >= *window * 2,
+            _ => true,
+        }
+    }
+}
+
+/// Outcome of a rate limit check, with what the client has left afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: usize,
+    pub remaining: usize,
+    /// Time until the allowance is replenished
+    pub reset_after: std::time::Duration,
 }
 
+#[derive(Debug)]
 pub struct RateLimiter {
-    limits: Arc<RwLock<HashMap<String, RateLimitInfo>>>,
-    daily_limit: usize,
+    rules: Vec<LimitRule>,
+    limits: Arc<RwLock<HashMap<String, Vec<RuleState>>>>,
     cleanup_interval: Duration,
     last_cleanup: Arc<RwLock<DateTime<Utc>>>,
+    rejections: AtomicU64,
 }
 
 impl RateLimiter {
+    /// At most `daily_limit` requests per client in any 24 hours.
     pub fn new(daily_limit: usize) -> Self {
+        Self::with_rules(vec![LimitRule::per_day(daily_limit)])
+    }
+
+    /// A request is allowed only if every rule allows it.
+    pub fn with_rules(rules: Vec<LimitRule>) -> Self {
         Self {
+            rules,
             limits: Arc::new(RwLock::new(HashMap::new())),
-            daily_limit,
             cleanup_interval: Duration::minutes(5),
             last_cleanup: Arc::new(RwLock::new(Utc::now())),
+            rejections: AtomicU64::new(0),
         }
     }
 
+    pub fn rules(&self) -> &[LimitRule] {
+        &self.rules
+    }
+
+    /// Requests refused since the limiter was created.
+    pub fn rejections(&self) -> u64 {
+        self.rejections.load(Ordering::Relaxed)
+    }
+
     pub async fn check_rate_limit(&self, key: &str) -> bool {
-        self.cleanup_if_needed().await;
-        
+        self.check(key).await.allowed
+    }
+
+    /// Counts a request against `key` if it is within every limit.
+    pub async fn check(&self, key: &str) -> RateLimitStatus {
+        self.check_at(key, Utc::now()).await
+    }
+
+    async fn check_at(&self, key: &str, now: DateTime<Utc>) -> RateLimitStatus {
+        self.cleanup_if_needed(now).await;
+
         let mut limits = self.limits.write().await;
-        let now = Utc::now();
-        
-        let info = limits.entry(key.to_string()).or_insert_with(|| RateLimitInfo {
-            requests: Vec::new(),
-            daily_count: 0,
-            last_reset: now,
-        });
-        
-        // Reset daily count if it's a new day
-        if info.last_reset.date_naive() < now.date_naive() {
-            info.daily_count = 0;
-            info.last_reset = now;
-            info.requests.clear();
+        let states = limits
+            .entry(key.to_string())
+            .or_insert_with(|| self.rules.iter().map(|rule| rule.new_state(now)).collect());
+
+        let decisions: Vec<RuleDecision> = states
+            .iter_mut()
+            .zip(&self.rules)
+            .map(|(state, rule)| {
+                state.advance(rule, now);
+                state.decide(rule, now)
+            })
+            .collect();
+
+        let allowed = decisions.iter().all(|decision| decision.allowed);
+        if allowed {
+            states.iter_mut().for_each(RuleState::consume);
+            debug!("Rate limit check passed for key: {}", key);
+        } else {
+            self.rejections.fetch_add(1, Ordering::Relaxed);
+            warn!("Rate limit exceeded for key: {}", key);
         }
-        
-        // Check if we're under the daily limit
-        if info.daily_count >= self.daily_limit {
-            warn!("Rate limit exceeded for key: {}. Daily count: {}", key, info.daily_count);
-            return false;
+        self.status(allowed, &decisions)
+    }
+
+    /// Reports the tightest rule: on refusal the one that keeps the client
+    /// waiting longest, otherwise the one with the fewest requests left.
+    fn status(&self, allowed: bool, decisions: &[RuleDecision]) -> RateLimitStatus {
+        let binding = decisions
+            .iter()
+            .zip(&self.rules)
+            .filter(|(decision, _)| allowed || !decision.allowed)
+            .min_by(|(a, _), (b, _)| {
+                if allowed {
+                    a.remaining.cmp(&b.remaining).then(b.reset_after.cmp(&a.reset_after))
+                } else {
+                    b.reset_after.cmp(&a.reset_after)
+                }
+            });
+
+        match binding {
+            Some((decision, rule)) => RateLimitStatus {
+                allowed,
+                limit: rule.limit(),
+                remaining: decision.remaining,
+                reset_after: decision.reset_after.to_std().unwrap_or_default(),
+            },
+            // No rules: nothing to limit
+            None => RateLimitStatus {
+                allowed: true,
+                limit: usize::MAX,
+                remaining: usize::MAX,
+                reset_after: std::time::Duration::ZERO,
+            },
         }
-        
-        // Update counters
-        info.requests.push(now);
-        info.daily_count += 1;
-        
-        debug!("Rate limit check passed for key: {}. Daily count: {}/{}", 
-            key, info.daily_count, self.daily_limit);
-        true
     }
 
-    async fn cleanup_if_needed(&self) {
+    async fn cleanup_if_needed(&self, now: DateTime<Utc>) {
         let mut last_cleanup = self.last_cleanup.write().await;
-        let now = Utc::now();
-        
+
         if now - *last_cleanup > self.cleanup_interval {
             let mut limits = self.limits.write().await;
-            // Use checked date arithmetic for safety
-            limits.retain(|_, info| {
-                info.last_reset.date_naive() >= now.date_naive()
-                    .pred_opt()
-                    .unwrap_or_else(|| now.date_naive())
+            // Forget clients whose every limit has fully recovered
+            limits.retain(|_, states| {
+                states.iter_mut().zip(&self.rules).any(|(state, rule)| {
+                    state.advance(rule, now);
+                    !state.is_idle(rule, now)
+                })
             });
             *last_cleanup = now;
         }
     }
 
     pub async fn get_remaining_requests(&self, key: &str) -> usize {
+        let now = Utc::now();
         let limits = self.limits.read().await;
-        if let Some(info) = limits.get(key) {
-            self.daily_limit.saturating_sub(info.daily_count)
-        } else {
-            self.daily_limit
+        match limits.get(key) {
+            Some(states) => states
+                .iter()
+                .zip(&self.rules)
+                .map(|(state, rule)| {
+                    let mut state = state.clone();
+                    state.advance(rule, now);
+                    // Remaining before this request, so add back the one `decide` assumes
+                    let decision = state.decide(rule, now);
+                    if decision.allowed { decision.remaining + 1 } else { 0 }
+                })
+                .min()
+                .unwrap_or(usize::MAX),
+            None => self.rules.iter().map(LimitRule::limit).min().unwrap_or(usize::MAX),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sliding_window_has_no_midnight_reset() {
+        let limiter = RateLimiter::with_rules(vec![LimitRule::per_window(4, Duration::hours(1))]);
+        let start = Utc::now();
+        for _ in 0..4 {
+            assert!(limiter.check_at("client", start).await.allowed);
+        }
+        let refused = limiter.check_at("client", start + Duration::minutes(30)).await;
+        assert!(!refused.allowed);
+        assert_eq!(refused.reset_after, std::time::Duration::from_secs(30 * 60));
+
+        // Halfway into the next window, half of the previous window still counts
+        let later = start + Duration::minutes(90);
+        assert!(limiter.check_at("client", later).await.allowed);
+        assert_eq!(limiter.check_at("client", later).await.remaining, 0);
+        assert!(!limiter.check_at("client", later).await.allowed);
+        assert!(limiter.check_at("other", later).await.allowed);
+        assert_eq!(limiter.rejections(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stacked_token_bucket_and_window() {
+        let rules = vec!["bucket:2@1/s".parse().unwrap(), "3/1d".parse().unwrap()];
+        let limiter = RateLimiter::with_rules(rules);
+        let start = Utc::now();
+
+        assert!(limiter.check_at("client", start).await.allowed);
+        let status = limiter.check_at("client", start).await;
+        assert!(status.allowed);
+        assert_eq!((status.limit, status.remaining), (2, 0));
+
+        // The burst is spent; one token comes back after a second
+        let refused = limiter.check_at("client", start).await;
+        assert!(!refused.allowed);
+        assert_eq!(refused.reset_after, std::time::Duration::from_secs(1));
+        assert!(limiter.check_at("client", start + Duration::seconds(1)).await.allowed);
+
+        // Tokens keep refilling, but the daily window is used up
+        let refused = limiter.check_at("client", start + Duration::seconds(10)).await;
+        assert!(!refused.allowed);
+        assert_eq!(refused.limit, 3);
+
+        assert_eq!("10/s".parse::<LimitRule>().unwrap(), LimitRule::per_window(10, Duration::seconds(1)));
+        assert!("10 per day".parse::<LimitRule>().is_err());
+    }
+}
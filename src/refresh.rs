@@ -0,0 +1,248 @@
+use crate::cache::{Cache, ExchangeRateData};
+use crate::clients::{QuotaMode, UpstreamQuota};
+use crate::config::{RateLimitSettings, RefreshSettings};
+use crate::currency_service::CurrencyService;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Scores below this are forgotten, so bases nobody asks for any more drop out.
+const MIN_POPULARITY: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy)]
+struct Score {
+    value: f64,
+    updated: DateTime<Utc>,
+}
+
+/// Rate lookups per base currency, decaying by half every `half_life`, so the
+/// ranking follows current traffic rather than all-time totals.
+#[derive(Debug)]
+pub struct BasePopularity {
+    half_life: Duration,
+    scores: Mutex<HashMap<String, Score>>,
+}
+
+impl BasePopularity {
+    pub fn new(half_life: Duration) -> Self {
+        Self {
+            half_life: half_life.max(Duration::seconds(1)),
+            scores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn decayed(&self, score: Score, now: DateTime<Utc>) -> f64 {
+        let elapsed = (now - score.updated).num_milliseconds().max(0) as f64;
+        score.value * 0.5f64.powf(elapsed / self.half_life.num_milliseconds() as f64)
+    }
+
+    pub fn record(&self, base: &str) {
+        self.record_at(base, Utc::now());
+    }
+
+    fn record_at(&self, base: &str, now: DateTime<Utc>) {
+        let mut scores = self.scores.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let value = scores.get(base).map_or(0.0, |score| self.decayed(*score, now)) + 1.0;
+        scores.insert(base.to_string(), Score { value, updated: now });
+    }
+
+    /// The `limit` bases with the highest current score, most popular first.
+    pub fn top(&self, limit: usize) -> Vec<String> {
+        self.top_at(limit, Utc::now())
+    }
+
+    fn top_at(&self, limit: usize, now: DateTime<Utc>) -> Vec<String> {
+        let mut scores = self.scores.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        scores.retain(|_, score| self.decayed(*score, now) >= MIN_POPULARITY);
+        let mut bases: Vec<(&String, f64)> = scores
+            .iter()
+            .map(|(base, score)| (base, self.decayed(*score, now)))
+            .collect();
+        bases.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        bases.into_iter().take(limit).map(|(base, _)| base.clone()).collect()
+    }
+}
+
+/// Upstream calls spent on refreshes during one UTC day.
+#[derive(Debug)]
+struct Budget {
+    day: NaiveDate,
+    spent: usize,
+}
+
+/// Refreshes the rate tables of the most requested base currencies shortly
+/// before they expire, so popular conversions never wait on upstream.
+pub struct RefreshScheduler {
+    service: Arc<CurrencyService>,
+    cache: Arc<Cache<ExchangeRateData>>,
+    popularity: Arc<BasePopularity>,
+    max_bases: usize,
+    refresh_ahead: Duration,
+    daily_budget: usize,
+    budget: Mutex<Budget>,
+    quota: Option<Arc<UpstreamQuota>>,
+}
+
+impl RefreshScheduler {
+    pub fn new(
+        service: Arc<CurrencyService>,
+        cache: Arc<Cache<ExchangeRateData>>,
+        popularity: Arc<BasePopularity>,
+        settings: &RefreshSettings,
+        rate_limits: &RateLimitSettings,
+    ) -> Self {
+        // Refreshes share the upstream plan with user traffic, so never exceed it
+        let daily_budget = settings.daily_budget.min(rate_limits.requests_per_day);
+        Self {
+            service,
+            cache,
+            popularity,
+            max_bases: settings.max_bases,
+            refresh_ahead: Duration::minutes(settings.refresh_ahead_minutes),
+            daily_budget,
+            budget: Mutex::new(Budget {
+                day: Utc::now().date_naive(),
+                spent: 0,
+            }),
+            quota: None,
+        }
+    }
+
+    /// Pauses refreshes while the upstream quota is running low.
+    pub fn with_quota(mut self, quota: Option<Arc<UpstreamQuota>>) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Takes one upstream call from today's budget, if any is left.
+    fn try_spend(&self) -> bool {
+        let mut budget = self.budget.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let today = Utc::now().date_naive();
+        if budget.day != today {
+            budget.day = today;
+            budget.spent = 0;
+        }
+        if budget.spent >= self.daily_budget {
+            return false;
+        }
+        budget.spent += 1;
+        true
+    }
+
+    /// Checks the popular base currencies once and refreshes those about to expire.
+    /// Returns the number of tables refreshed.
+    pub async fn run_once(&self) -> usize {
+        if let Some(quota) = &self.quota {
+            if quota.mode().await != QuotaMode::Normal {
+                debug!("Skipping background refresh while the upstream quota is low");
+                return 0;
+            }
+        }
+
+        let mut refreshed = 0;
+        for base in self.popularity.top(self.max_bases) {
+            let due = match self.cache.time_to_expiry(&base).await {
+                Some(remaining) => remaining <= self.refresh_ahead,
+                None => true,
+            };
+            if !due {
+                continue;
+            }
+            if !self.try_spend() {
+                warn!("Background refresh budget of {} calls per day exhausted", self.daily_budget);
+                break;
+            }
+
+            match self.service.refresh_rates(&base).await {
+                Ok(()) => {
+                    debug!("Refreshed popular base currency {} ahead of expiry", base);
+                    refreshed += 1;
+                }
+                Err(e) => warn!("Background refresh for {} failed: {}", base, e),
+            }
+        }
+        refreshed
+    }
+
+    /// Runs `run_once` every `interval`, forever.
+    pub async fn run(self, interval: std::time::Duration) {
+        info!(
+            "Refreshing up to {} popular base currencies, budget {} calls per day",
+            self.max_bases, self.daily_budget
+        );
+        loop {
+            tokio::time::sleep(interval).await;
+            self.run_once().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::tests::MockClient;
+    use crate::models::ExchangeRateResponse;
+
+    fn usd_rates() -> ExchangeRateResponse {
+        ExchangeRateResponse {
+            result: "success".to_string(),
+            conversion_rates: [("EUR".to_string(), 0.9)].into_iter().collect(),
+            time_last_update_utc: None,
+            source: "mock".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refreshes_popular_bases_within_budget() {
+        // Zero TTL, so every cached table is always due for a refresh
+        let cache = Arc::new(Cache::new(0, 100).with_stale_window(60));
+        let popularity = Arc::new(BasePopularity::new(Duration::hours(1)));
+        let service = Arc::new(CurrencyService::new(
+            MockClient::new().with_rate_response(usd_rates()),
+            Arc::clone(&cache),
+        ));
+        for base in ["USD", "USD", "EUR", "GBP"] {
+            popularity.record(base);
+        }
+
+        let settings = RefreshSettings {
+            max_bases: 2,
+            daily_budget: 3,
+            ..Default::default()
+        };
+        let scheduler = RefreshScheduler::new(
+            service,
+            Arc::clone(&cache),
+            popularity,
+            &settings,
+            &RateLimitSettings::default(),
+        );
+
+        assert_eq!(scheduler.run_once().await, 2);
+        assert!(cache.get_stale("USD").await.is_some());
+        assert!(cache.get_stale("EUR").await.is_some());
+        assert!(cache.get_stale("GBP").await.is_none());
+
+        // Only one call is left in today's budget
+        assert_eq!(scheduler.run_once().await, 1);
+        assert_eq!(scheduler.run_once().await, 0);
+    }
+
+    #[test]
+    fn test_popularity_decays() {
+        let popularity = BasePopularity::new(Duration::hours(1));
+        let start = Utc::now();
+        for _ in 0..8 {
+            popularity.record_at("USD", start);
+        }
+        popularity.record_at("EUR", start + Duration::hours(3));
+        popularity.record_at("EUR", start + Duration::hours(3));
+
+        // 8 lookups three half-lives ago weigh as much as 1 now, so EUR leads
+        assert_eq!(popularity.top_at(2, start + Duration::hours(3)), vec!["EUR", "USD"]);
+
+        // Bases nobody asked for in a long time are forgotten
+        assert_eq!(popularity.top_at(2, start + Duration::hours(10)), vec!["EUR"]);
+    }
+}
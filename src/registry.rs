@@ -1,56 +1,389 @@
 use crate::{
-    cache::{Cache, ExchangeRateData},
-    clients::HttpClient,
+    auth::{AdminKeyStore, FileKeyStore, KeyStore},
+    cache::{Cache, CacheStatsSource, ExchangeRateData},
+    cache_backend::{CacheBackendKind, RedisBackend, RedisConnection},
+    clients::{
+        offline::OFFLINE_COUNTRY_SOURCE, CachingCountryClient, CircuitBreakerClient, CircuitBreakers,
+        CountryClient, ExchangeRateClient, FallbackCountryClient, HttpClient, OfflineCountryClient,
+        providers::EXCHANGE_RATE_API_PROVIDER, ProviderRegistry, RetryPolicy, TimedClient, UpstreamQuota, REST_COUNTRIES_UPSTREAM,
+    },
     currency_service::CurrencyService,
     errors::ServiceError,
-    config::Config,
+    config::{CacheSettings, Config},
+    models::CountryInfo,
+    monitor::{UsageMonitor, COUNTRIES_DEPENDENCY, RATES_DEPENDENCY},
+    persistence::CacheSnapshots,
+    rate_limit::RateLimiter,
+    refresh::BasePopularity,
 };
-use std::{sync::Arc, time::Duration};
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Names the caches are reported under in usage stats.
+pub const EXCHANGE_RATE_CACHE: &str = "exchange_rates";
+pub const COUNTRY_CACHE: &str = "countries";
+pub const COUNTRY_NOT_FOUND_CACHE: &str = "countries_not_found";
+
+/// Snapshot file names, without extension, for each persisted cache.
+const EXCHANGE_RATE_SNAPSHOT: &str = EXCHANGE_RATE_CACHE;
+const COUNTRY_SNAPSHOT: &str = COUNTRY_CACHE;
+
+/// Upstream quota counters, kept next to the snapshots unless configured otherwise.
+const QUOTA_STATE_FILE: &str = "upstream_quota.json";
 
 pub struct ServiceRegistry {
-    pub currency_service: Arc<CurrencyService<HttpClient>>,
+    pub currency_service: Arc<CurrencyService>,
+    /// Decaying rate lookups per base currency, ranking tables for background refresh
+    pub popularity: Arc<BasePopularity>,
     pub cache: Arc<Cache<ExchangeRateData>>,
+    pub country_cache: Arc<Cache<CountryInfo>>,
+    pub monitor: Arc<UsageMonitor>,
+    pub circuit_breakers: Arc<CircuitBreakers>,
+    /// Budget for exchangerate-api calls, when that provider is in use
+    pub quota: Option<Arc<UpstreamQuota>>,
+    /// Client API keys; `None` leaves the endpoints open
+    pub key_store: Option<Arc<dyn KeyStore>>,
+    /// Keys accepted by `/admin`: the operator key and client keys whose tier
+    /// includes it. With neither configured it refuses every key, so `/admin` stays closed
+    pub admin_key_store: Arc<dyn KeyStore>,
+    snapshots: Option<CacheSnapshots>,
+    client_rate_limiters: HashMap<String, Arc<RateLimiter>>,
+}
+
+/// The exchange rate cache and upstream quota, which the rate client is built
+/// around, and the Redis connection the other caches share with them.
+struct RateStorage {
+    redis: Option<Arc<RedisConnection>>,
+    cache: Arc<Cache<ExchangeRateData>>,
+    quota: Option<Arc<UpstreamQuota>>,
 }
 
 impl ServiceRegistry {
     pub fn new(config: &Config) -> Result<Self, ServiceError> {
-        // Initialize cache
-        let cache = Arc::new(Cache::new(
-            config.cache_settings.exchange_rate_ttl_minutes,
-            1000 // max entries
-        ));
+        // Guard every upstream with its own circuit breaker
+        let circuit_breakers = Arc::new(CircuitBreakers::new(config.circuit_breaker_settings.clone()));
+        let monitor = Arc::new(UsageMonitor::new());
+        let country_client = Self::build_country_client(config, &circuit_breakers, &monitor)?;
+
+        // Initialize the configured exchange rate providers as a failover chain,
+        // with only the metered provider spending the upstream plan
+        let storage = Self::build_rate_storage(config, Self::build_quota(config))?;
+        let providers = match &storage.quota {
+            Some(quota) => ProviderRegistry::with_defaults().with_quota(Arc::clone(quota), Arc::clone(&storage.cache)),
+            None => ProviderRegistry::with_defaults(),
+        };
+        let rate_client = providers.build_chain(
+            &config.provider_settings.provider_chain(),
+            config,
+            Arc::clone(&monitor),
+            &circuit_breakers,
+        )?;
+
+        Self::from_parts(
+            config,
+            country_client,
+            Arc::new(rate_client),
+            storage,
+            monitor,
+            circuit_breakers,
+        )
+    }
 
-        // Initialize HTTP client
+    /// Country lookups go to restcountries.com, the bundled dataset, or both.
+    fn build_country_client(
+        config: &Config,
+        circuit_breakers: &CircuitBreakers,
+        monitor: &Arc<UsageMonitor>,
+    ) -> Result<Arc<dyn CountryClient>, ServiceError> {
+        let settings = &config.provider_settings;
+        if settings.country_source == OFFLINE_COUNTRY_SOURCE {
+            return Ok(Arc::new(OfflineCountryClient::new()));
+        }
+        if settings.country_source != REST_COUNTRIES_UPSTREAM {
+            return Err(ServiceError::ConfigError(format!(
+                "Unknown country source: {}", settings.country_source
+            )));
+        }
+
+        // Initialize HTTP client for country lookups
         let http_client = HttpClient::with_timeouts(
-            Duration::from_secs(30),
+            Duration::from_secs(config.upstream_settings.timeout_seconds),
             config.exchange_rate_api_key.clone()
-        )?;
+        )?
+        .with_base_urls(
+            &config.upstream_settings.rest_countries_base_url,
+            &config.upstream_settings.exchange_rate_api_base_url,
+        )
+        .with_retry_policy(RetryPolicy::from_settings(&config.retry_settings));
+
+        // Timed beneath the breaker and fallback, so only calls that reach restcountries count
+        let http_client: Arc<dyn CountryClient> = Arc::new(
+            TimedClient::new(Arc::new(http_client), COUNTRIES_DEPENDENCY, Arc::clone(monitor))
+                .with_provider(REST_COUNTRIES_UPSTREAM),
+        );
+        let country_client: Arc<dyn CountryClient> = Arc::new(CircuitBreakerClient::new(
+            http_client,
+            circuit_breakers.get(REST_COUNTRIES_UPSTREAM),
+        ));
+
+        if settings.offline_country_fallback {
+            Ok(Arc::new(FallbackCountryClient::new(
+                country_client,
+                Arc::new(OfflineCountryClient::new()),
+            )))
+        } else {
+            Ok(country_client)
+        }
+    }
+
+    /// Builds the registry around an already constructed client, e.g. a mock in tests.
+    pub fn with_client<C>(config: &Config, client: C) -> Result<Self, ServiceError>
+    where
+        C: CountryClient + ExchangeRateClient + 'static,
+    {
+        let client = Arc::new(client);
+        Self::with_clients(config, client.clone(), client)
+    }
+
+    pub fn with_clients(
+        config: &Config,
+        country_client: Arc<dyn CountryClient>,
+        rate_client: Arc<dyn ExchangeRateClient>,
+    ) -> Result<Self, ServiceError> {
+        // Injected clients stand in for the upstreams, so they are timed as such;
+        // they are not the metered provider, so nothing is charged to a quota
+        let monitor = Arc::new(UsageMonitor::new());
+        let country_client: Arc<dyn CountryClient> =
+            Arc::new(TimedClient::new(country_client, COUNTRIES_DEPENDENCY, Arc::clone(&monitor)));
+        let rate_client: Arc<dyn ExchangeRateClient> =
+            Arc::new(TimedClient::new(rate_client, RATES_DEPENDENCY, Arc::clone(&monitor)));
+        Self::from_parts(
+            config,
+            country_client,
+            rate_client,
+            Self::build_rate_storage(config, None)?,
+            monitor,
+            Arc::new(CircuitBreakers::new(config.circuit_breaker_settings.clone())),
+        )
+    }
+
+    fn from_parts(
+        config: &Config,
+        country_client: Arc<dyn CountryClient>,
+        rate_client: Arc<dyn ExchangeRateClient>,
+        storage: RateStorage,
+        monitor: Arc<UsageMonitor>,
+        circuit_breakers: Arc<CircuitBreakers>,
+    ) -> Result<Self, ServiceError> {
+        let RateStorage { redis, cache, quota } = storage;
+        let settings = &config.cache_settings;
+        let country_cache = Arc::new(Self::with_cache_backend(
+            Cache::new(settings.country_info_ttl_minutes, 500) // max entries
+                .with_eviction_policy(settings.eviction_policy),
+            redis.as_ref(),
+            settings,
+            COUNTRY_CACHE,
+        ));
+
+        // Country lookups are served from the cache, unknown names included
+        let country_not_found_cache = Arc::new(Self::with_cache_backend(
+            Cache::new(settings.country_not_found_ttl_minutes, 500),
+            redis.as_ref(),
+            settings,
+            COUNTRY_NOT_FOUND_CACHE,
+        ));
+        let country_client: Arc<dyn CountryClient> = Arc::new(CachingCountryClient::new(
+            country_client,
+            Arc::clone(&country_cache),
+            Arc::clone(&country_not_found_cache),
+        ));
+
+        monitor.register_cache(EXCHANGE_RATE_CACHE, Arc::clone(&cache) as Arc<dyn CacheStatsSource>);
+        monitor.register_cache(COUNTRY_CACHE, Arc::clone(&country_cache) as Arc<dyn CacheStatsSource>);
+        monitor.register_cache(COUNTRY_NOT_FOUND_CACHE, country_not_found_cache as Arc<dyn CacheStatsSource>);
 
         // Initialize currency service
+        let popularity = Arc::new(BasePopularity::new(chrono::Duration::minutes(
+            config.refresh_settings.popularity_half_life_minutes,
+        )));
         let currency_service = Arc::new(
-            CurrencyService::new(
-                http_client,
+            CurrencyService::with_clients(
+                country_client,
+                rate_client,
                 Arc::clone(&cache)
             )
+            .with_monitor(Arc::clone(&monitor))
+            .with_popularity(Arc::clone(&popularity))
         );
 
+        let persistence = &config.persistence_settings;
+        let snapshots = persistence
+            .snapshot_dir
+            .as_ref()
+            .map(|dir| CacheSnapshots::new(dir, persistence.snapshot_format));
+
+        // One limiter per route scope, shared by every worker
+        let client_limits = &config.client_rate_limit_settings;
+        let client_rate_limiters = if client_limits.enabled {
+            client_limits
+                .scope_limits
+                .iter()
+                .map(|(scope, rules)| (scope.clone(), Arc::new(RateLimiter::with_rules(rules.clone()))))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        for (scope, limiter) in &client_rate_limiters {
+            monitor.register_rate_limiter(scope, Arc::clone(limiter));
+        }
+
+        let key_store = match &config.auth_settings.api_keys_file {
+            Some(path) => {
+                let store = FileKeyStore::from_path(path)?;
+                info!("Loaded {} client API keys from {}", store.len(), path);
+                for tier in store.tiers() {
+                    if let Some(limiter) = &tier.limiter {
+                        monitor.register_rate_limiter(&format!("tier:{}", tier.name), Arc::clone(limiter));
+                    }
+                }
+                Some(Arc::new(store) as Arc<dyn KeyStore>)
+            }
+            None => None,
+        };
+        let admin_key_store: Arc<dyn KeyStore> = match &config.auth_settings.admin_api_key {
+            Some(api_key) => Arc::new(AdminKeyStore::new(api_key, key_store.clone())),
+            None => {
+                if key_store.is_none() {
+                    warn!("Admin endpoints are closed; set ADMIN_API_KEY or API_KEYS_FILE to open them");
+                }
+                Arc::new(AdminKeyStore::without_operator(key_store.clone()))
+            }
+        };
+
         Ok(Self {
             currency_service,
+            popularity,
             cache,
+            country_cache,
+            monitor,
+            circuit_breakers,
+            quota,
+            key_store,
+            admin_key_store,
+            snapshots,
+            client_rate_limiters,
         })
     }
 
-    // Add cleanup method for graceful shutdown
+    /// Builds the exchange rate cache, on Redis when configured, ahead of the
+    /// rate client so the quota can check it for tables to fall back on.
+    fn build_rate_storage(config: &Config, quota: Option<Arc<UpstreamQuota>>) -> Result<RateStorage, ServiceError> {
+        let settings = &config.cache_settings;
+        let redis = Self::connect_redis(settings)?;
+        let cache = Arc::new(Self::with_cache_backend(
+            Cache::new(settings.exchange_rate_ttl_minutes, 1000) // max entries
+                .with_eviction_policy(settings.eviction_policy)
+                .with_stale_window(settings.stale_window_minutes),
+            redis.as_ref(),
+            settings,
+            EXCHANGE_RATE_CACHE,
+        ));
+        Ok(RateStorage { redis, cache, quota })
+    }
+
+    /// Only exchangerate-api.com bills per call, so other providers go untracked.
+    fn build_quota(config: &Config) -> Option<Arc<UpstreamQuota>> {
+        let settings = &config.quota_settings;
+        let metered = config.provider_settings.provider_chain().iter().any(|p| p == EXCHANGE_RATE_API_PROVIDER);
+        if !settings.enabled || !metered {
+            return None;
+        }
+
+        let quota = UpstreamQuota::new(config.rate_limit_settings.requests_per_day, settings);
+        let state_path = settings.state_path.as_ref().map(PathBuf::from).or_else(|| {
+            let dir = config.persistence_settings.snapshot_dir.as_ref()?;
+            Some(Path::new(dir).join(QUOTA_STATE_FILE))
+        });
+        Some(Arc::new(match state_path {
+            Some(path) => quota.with_state_path(path),
+            None => quota,
+        }))
+    }
+
+    /// Limiter for requests to a route scope such as `/v1`, if that scope is limited.
+    pub fn client_rate_limiter(&self, scope: &str) -> Option<Arc<RateLimiter>> {
+        self.client_rate_limiters.get(scope).cloned()
+    }
+
+    fn connect_redis(settings: &CacheSettings) -> Result<Option<Arc<RedisConnection>>, ServiceError> {
+        match settings.backend {
+            CacheBackendKind::Memory => Ok(None),
+            CacheBackendKind::Redis => {
+                let url = settings.redis_url.as_deref().ok_or_else(|| {
+                    ServiceError::ConfigError("REDIS_URL is required for the redis cache backend".to_string())
+                })?;
+                Ok(Some(Arc::new(RedisConnection::open(url)?)))
+            }
+        }
+    }
+
+    /// Moves the cache onto the shared Redis server when one is configured.
+    fn with_cache_backend<T>(
+        cache: Cache<T>,
+        redis: Option<&Arc<RedisConnection>>,
+        settings: &CacheSettings,
+        name: &str,
+    ) -> Cache<T>
+    where
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        match redis {
+            Some(redis) => {
+                let namespace = format!("{}:{}", settings.redis_key_prefix, name);
+                cache.with_backend(Arc::new(RedisBackend::new(Arc::clone(redis), &namespace)))
+            }
+            None => cache,
+        }
+    }
+
+    /// Warms the caches from their snapshots, if persistence is configured.
+    pub async fn restore_snapshots(&self) {
+        let Some(snapshots) = &self.snapshots else { return };
+        if let Err(e) = snapshots.load(&self.cache, EXCHANGE_RATE_SNAPSHOT).await {
+            warn!("Failed to restore exchange rate cache: {}", e);
+        }
+        if let Err(e) = snapshots.load(&self.country_cache, COUNTRY_SNAPSHOT).await {
+            warn!("Failed to restore country cache: {}", e);
+        }
+    }
+
+    /// Writes the caches to their snapshots, if persistence is configured.
+    pub async fn save_snapshots(&self) {
+        let Some(snapshots) = &self.snapshots else { return };
+        if let Err(e) = snapshots.save(&self.cache, EXCHANGE_RATE_SNAPSHOT).await {
+            warn!("Failed to persist exchange rate cache: {}", e);
+        }
+        if let Err(e) = snapshots.save(&self.country_cache, COUNTRY_SNAPSHOT).await {
+            warn!("Failed to persist country cache: {}", e);
+        }
+    }
+
+    /// Flushes state that should survive a restart; called on graceful shutdown.
     pub async fn cleanup(&self) {
-        // Add any cleanup logic here
-        // For example: closing connections, flushing caches, etc.
+        self.save_snapshots().await;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{Config, CacheSettings, RateLimitSettings};
+    use crate::config::{Config, CacheSettings, PersistenceSettings, RateLimitSettings};
 
     #[tokio::test]
     async fn test_registry_creation() {
@@ -60,14 +393,47 @@ mod tests {
                 exchange_rate_ttl_minutes: 60,
                 country_info_ttl_minutes: 1440,
                 cache_cleanup_interval_minutes: 5,
+                ..Default::default()
             },
             rate_limit_settings: RateLimitSettings {
                 requests_per_day: 1000,
                 window_size_minutes: 1440,
             },
+            ..Default::default()
         };
 
         let registry = ServiceRegistry::new(&config).unwrap();
         assert!(Arc::strong_count(&registry.cache) >= 2); // At least two references: registry and service
     }
+
+    #[tokio::test]
+    async fn test_cleanup_persists_caches_for_next_start() {
+        let dir = std::env::temp_dir().join(format!("registry-snapshots-{}", uuid::Uuid::new_v4()));
+        let config = Config {
+            exchange_rate_api_key: "test_key".to_string(),
+            persistence_settings: PersistenceSettings {
+                snapshot_dir: Some(dir.to_string_lossy().into_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let registry = ServiceRegistry::new(&config).unwrap();
+        registry.cache.set(
+            "USD".to_string(),
+            ExchangeRateData {
+                base: "USD".to_string(),
+                rates: [("EUR".to_string(), 0.9)].into_iter().collect(),
+                last_updated: chrono::Utc::now(),
+                source: "test".to_string(),
+            },
+        ).await;
+        registry.cleanup().await;
+
+        let restarted = ServiceRegistry::new(&config).unwrap();
+        restarted.restore_snapshots().await;
+        let table = restarted.cache.get("USD").await.unwrap();
+        assert_eq!(table.rate("EUR"), Some(0.9));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
\ No newline at end of file
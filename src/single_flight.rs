@@ -0,0 +1,106 @@
+use crate::errors::ServiceError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::OnceCell;
+
+type Flight<T> = Arc<OnceCell<Result<T, ServiceError>>>;
+
+/// Collapses concurrent calls for the same key into a single in-flight call.
+///
+/// Callers arriving while a call is running wait for it and share its result or
+/// error instead of starting their own. Once the call finishes the key is free
+/// again, so results are never reused beyond the callers that overlapped.
+pub struct SingleFlight<T> {
+    flights: Mutex<HashMap<String, Flight<T>>>,
+}
+
+impl<T: Clone> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self {
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Flight<T>>> {
+        self.flights.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Runs `call` unless one is already in flight for `key`.
+    /// The flag is true when the caller waited on another caller's call.
+    pub async fn run<F, Fut>(&self, key: &str, call: F) -> (Result<T, ServiceError>, bool)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, ServiceError>>,
+    {
+        let (flight, coalesced) = {
+            let mut flights = self.lock();
+            match flights.get(key) {
+                Some(flight) => (Arc::clone(flight), true),
+                None => {
+                    let flight: Flight<T> = Arc::new(OnceCell::new());
+                    flights.insert(key.to_string(), Arc::clone(&flight));
+                    (flight, false)
+                }
+            }
+        };
+
+        // If the original caller is cancelled, a waiter takes over the call
+        let result = flight.get_or_init(call).await.clone();
+
+        let mut flights = self.lock();
+        if flights.get(key).is_some_and(|current| Arc::ptr_eq(current, &flight)) {
+            flights.remove(key);
+        }
+        (result, coalesced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_one_result() {
+        let flights = Arc::new(SingleFlight::<u32>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..5)
+            .map(|_| {
+                let flights = Arc::clone(&flights);
+                let calls = Arc::clone(&calls);
+                tokio::spawn(async move {
+                    flights
+                        .run("USD", || async {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Err(ServiceError::ServiceUnavailable("down".to_string()))
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        let mut coalesced = 0;
+        for handle in handles {
+            let (result, waited) = handle.await.unwrap();
+            assert!(matches!(result, Err(ServiceError::ServiceUnavailable(_))));
+            coalesced += waited as usize;
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(coalesced, 4);
+
+        // The key is released once the call completes
+        let (result, waited) = flights.run("USD", || async { Ok(7) }).await;
+        assert_eq!(result.unwrap(), 7);
+        assert!(!waited);
+    }
+}
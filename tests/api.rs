@@ -1,61 +1,26 @@
-use actix_web::{test, web, App};
-use currency_converter::{handlers, handlers_v1, models::*};
-use log::debug;
+mod common;
+
+use actix_web::{test, web};
+use currency_converter::{
+    app::build_app,
+    models::*,
+    config::Config,
+    middleware::{API_KEY_HEADER, RATE_LIMIT_REMAINING},
+    registry::ServiceRegistry,
+};
 use serde_json::json;
-use std::sync::{Arc, Mutex, Once};
-use std::env;
+use std::sync::Once;
 
 static INIT: Once = Once::new();
-static API_KEY_INIT: Once = Once::new();
-static mut ORIGINAL_API_KEY: Option<Arc<Mutex<Option<String>>>> = None;
-
-fn save_api_key() -> Arc<Mutex<Option<String>>> {
-    let key_storage = Arc::new(Mutex::new(None));
-    unsafe {
-        API_KEY_INIT.call_once(|| {
-            ORIGINAL_API_KEY = Some(key_storage.clone());
-        });
-        if let Some(original) = env::var("EXCHANGE_RATE_API_KEY").ok() {
-            *key_storage.lock().unwrap() = Some(original);
-        }
-    }
-    key_storage
-}
-
-fn restore_api_key(key_storage: &Arc<Mutex<Option<String>>>) {
-    if let Some(key) = key_storage.lock().unwrap().as_ref() {
-        env::set_var("EXCHANGE_RATE_API_KEY", key);
-    }
-}
 
 /// Ensures test environment is properly configured
 fn setup_test_env() {
     INIT.call_once(|| {
-        std::env::set_var("RUST_LOG", "debug");
-        env_logger::init();
+        let _ = env_logger::builder().is_test(true).try_init();
     });
-
-    // Try to load API key from different sources
-    if env::var("EXCHANGE_RATE_API_KEY").is_err() {
-        // Try loading from .env files
-        for env_file in &[".env", ".env.test", ".env.testing"] {
-            if dotenv::from_filename(env_file).is_ok() {
-                if env::var("EXCHANGE_RATE_API_KEY").is_ok() {
-                    debug!("Loaded API key from {}", env_file);
-                    return;
-                }
-            }
-        }
-
-        // If no API key is found, panic with helpful message
-        panic!("No exchange rate API key found. Please ensure one of the following:
-            1. EXCHANGE_RATE_API_KEY is set in the environment
-            2. .env file exists with EXCHANGE_RATE_API_KEY
-            3. .env.test file exists with EXCHANGE_RATE_API_KEY
-            4. .env.testing file exists with EXCHANGE_RATE_API_KEY");
-    }
 }
 
+/// Builds the app against a freshly started fake upstream.
 fn build_test_app() -> actix_web::App<
     impl actix_web::dev::ServiceFactory<
         actix_web::dev::ServiceRequest,
@@ -65,29 +30,27 @@ fn build_test_app() -> actix_web::App<
         InitError = ()
     >
 > {
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-        .expect("Failed to create HTTP client");
-    
-    let client_data = web::Data::new(client);
-
-    App::new()
-        .app_data(client_data.clone())
-        .service(
-            web::resource("/currency")
-                .route(web::post().to(handlers::convert_currency))
-        )
-        .service(
-            web::resource("/v1/currency")
-                .route(web::post().to(handlers_v1::convert_currency))
-        )
+    let base_url = common::start_fake_upstream();
+    build_test_app_with_config(&common::test_config(&base_url))
+}
+
+fn build_test_app_with_config(config: &Config) -> actix_web::App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Response = actix_web::dev::ServiceResponse<actix_web::body::BoxBody>,
+        Config = (),
+        Error = actix_web::Error,
+        InitError = ()
+    >
+> {
+    let registry = ServiceRegistry::new(config)
+        .expect("Failed to initialize services");
+    build_app(web::Data::new(registry), &config.client_rate_limit_settings)
 }
 
 #[actix_web::test]
 async fn test_simple_endpoint_valid_conversion() {
     setup_test_env();
-    let key_storage = save_api_key();
     let app = test::init_service(build_test_app()).await;
 
     let req = test::TestRequest::post()
@@ -110,7 +73,6 @@ async fn test_simple_endpoint_valid_conversion() {
     );
 
     let body: SimpleConversionResponse = test::read_body_json(resp).await;
-    restore_api_key(&key_storage);
     
     assert_eq!(body.from, "USD");
     assert_eq!(body.to, "EUR");
@@ -120,7 +82,6 @@ async fn test_simple_endpoint_valid_conversion() {
 #[actix_web::test]
 async fn test_simple_endpoint_invalid_country() {
     setup_test_env();
-    let key_storage = save_api_key();
     let app = test::init_service(build_test_app()).await;
 
     let req = test::TestRequest::post()
@@ -136,7 +97,6 @@ async fn test_simple_endpoint_invalid_country() {
     let resp = test::call_service(&app, req).await;
 
     let body: SimpleConversionResponse = test::read_body_json(resp).await;
-    restore_api_key(&key_storage);
     
     assert_eq!(body.from, "INVALID");
     assert_eq!(body.to, "INVALID");
@@ -146,7 +106,6 @@ async fn test_simple_endpoint_invalid_country() {
 #[actix_web::test]
 async fn test_case_sensitivity() {
     setup_test_env();
-    let key_storage = save_api_key();
     let app = test::init_service(build_test_app()).await;
 
     let req = test::TestRequest::post()
@@ -163,7 +122,6 @@ async fn test_case_sensitivity() {
     assert!(resp.status().is_success());
 
     let body: SimpleConversionResponse = test::read_body_json(resp).await;
-    restore_api_key(&key_storage);
 
     assert_eq!(body.from, "JPY");
     assert_eq!(body.to, "AUD");
@@ -173,7 +131,6 @@ async fn test_case_sensitivity() {
 #[actix_web::test]
 async fn test_v1_endpoint_valid_conversion() {
     setup_test_env();
-    let key_storage = save_api_key();
     let app = test::init_service(build_test_app()).await;
 
     let req = test::TestRequest::post()
@@ -194,6 +151,7 @@ async fn test_v1_endpoint_valid_conversion() {
         "Response status: {}, expected success",
         status
     );
+    assert_eq!(resp.headers().get(RATE_LIMIT_REMAINING).unwrap(), "19");
 
     // Use bytes first to debug the response if needed
     let body_bytes = test::read_body(resp).await;
@@ -203,7 +161,6 @@ async fn test_v1_endpoint_valid_conversion() {
     let body: DetailedConversionResponse = serde_json::from_str(&body_str)
         .unwrap_or_else(|e| panic!("Failed to parse response: {}. Response was: {}", e, body_str));
 
-    restore_api_key(&key_storage);
     
     assert_eq!(body.data.from.currency_code, "USD");
     assert_eq!(body.data.to.currency_code, "EUR");
@@ -211,12 +168,12 @@ async fn test_v1_endpoint_valid_conversion() {
     assert!(body.data.exchange_rate > 0.0);
     assert!(!body.request_id.is_empty());
     assert_eq!(body.meta.source, "exchangerate-api.com");
+    assert_eq!(body.meta.rate_limit_remaining, Some(19));
 }
 
 #[actix_web::test]
 async fn test_v1_endpoint_invalid_country() {
     setup_test_env();
-    let key_storage = save_api_key();  // Add this
     let app = test::init_service(build_test_app()).await;
 
     let req = test::TestRequest::post()
@@ -241,7 +198,6 @@ async fn test_v1_endpoint_invalid_country() {
     let body: DetailedErrorResponse = serde_json::from_str(&body_str)
         .unwrap_or_else(|e| panic!("Failed to parse response: {}. Response was: {}", e, body_str));
 
-    restore_api_key(&key_storage);  // Add this
     
     assert_eq!(status.as_u16(), 400);
     assert!(body.error.contains("Country not found: Narnia"));
@@ -251,11 +207,13 @@ async fn test_v1_endpoint_invalid_country() {
 #[actix_web::test]
 async fn test_service_errors() {
     setup_test_env();
-    let key_storage = save_api_key();
-    let app = test::init_service(build_test_app()).await;
-
-    // Remove API key to force error
-    env::remove_var("EXCHANGE_RATE_API_KEY");
+    // Use a key the exchange rate API rejects to force an upstream error
+    let base_url = common::start_fake_upstream();
+    let config = Config {
+        exchange_rate_api_key: "invalid_key".to_string(),
+        ..common::test_config(&base_url)
+    };
+    let app = test::init_service(build_test_app_with_config(&config)).await;
 
     let req = test::TestRequest::post()
         .uri("/currency")
@@ -268,12 +226,250 @@ async fn test_service_errors() {
         .to_request();
 
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status().as_u16(), 503, "Expected 503 Service Unavailable when API key is missing");
+    assert_eq!(resp.status().as_u16(), 503, "Expected 503 Service Unavailable when API key is rejected");
 
     let body: SimpleConversionResponse = test::read_body_json(resp).await;
-    restore_api_key(&key_storage);
     
     assert_eq!(body.from, "ERROR");
     assert_eq!(body.to, "ERROR");
     assert_eq!(body.amount, 0.0);
-}
\ No newline at end of file
+}
+
+#[actix_web::test]
+async fn test_offline_country_fallback() {
+    setup_test_env();
+    // Countries resolve from the bundled dataset while restcountries is unreachable
+    let base_url = common::start_fake_upstream();
+    let mut config = common::test_config(&base_url);
+    config.upstream_settings.rest_countries_base_url = "http://127.0.0.1:1/v3.1".to_string();
+    config.retry_settings.max_attempts = 1;
+    let app = test::init_service(build_test_app_with_config(&config)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/currency")
+        .insert_header(("content-type", "application/json"))
+        .set_payload(json!({
+            "from": "USA",
+            "to": "Germany",
+            "amount": 100.0
+        }).to_string())
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let body: SimpleConversionResponse = test::read_body_json(resp).await;
+
+    assert_eq!(body.from, "USD");
+    assert_eq!(body.to, "EUR");
+    assert_eq!(body.amount, 90.0);
+}
+
+#[actix_web::test]
+async fn test_admin_cache_stats() {
+    setup_test_env();
+    // Without an operator key or client keys the admin endpoints stay closed
+    let closed = test::init_service(build_test_app()).await;
+    let req = test::TestRequest::get().uri("/admin/cache").to_request();
+    assert_eq!(test::call_service(&closed, req).await.status(), 401);
+
+    let mut config = common::test_config(&common::start_fake_upstream());
+    config.auth_settings.admin_api_key = Some("operator-key".to_string());
+    let app = test::init_service(build_test_app_with_config(&config)).await;
+
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/currency")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(json!({
+                "from": "USA",
+                "to": "France",
+                "amount": 100.0
+            }).to_string())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/admin/cache")
+        .insert_header((API_KEY_HEADER, "operator-key"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let rates = &body["exchange_rates"];
+    assert_eq!(rates["inserts"], 1);
+    assert_eq!(rates["hits"], 1);
+    assert_eq!(rates["size"], 1);
+    assert_eq!(rates["age_distribution"]["under_1m"], 1);
+    // Both countries were looked up once and served from the cache the second time
+    assert_eq!(body["countries"]["inserts"], 2);
+    assert_eq!(body["countries"]["hits"], 2);
+    assert_eq!(body["countries_not_found"]["size"], 0);
+}
+
+#[actix_web::test]
+async fn test_admin_quota_status() {
+    setup_test_env();
+    let mut config = common::test_config(&common::start_fake_upstream());
+    config.auth_settings.admin_api_key = Some("operator-key".to_string());
+    let app = test::init_service(build_test_app_with_config(&config)).await;
+
+    for to in ["France", "Japan"] {
+        let req = test::TestRequest::post()
+            .uri("/currency")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(json!({
+                "from": "United States",
+                "to": to,
+                "amount": 100.0
+            }).to_string())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    // The second conversion reuses the cached USD table
+    let req = test::TestRequest::get()
+        .uri("/admin/quota")
+        .insert_header((API_KEY_HEADER, "operator-key"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["mode"], "normal");
+    assert_eq!(body["daily"]["used"], 1);
+    assert_eq!(body["daily"]["remaining"], 999);
+    assert_eq!(body["monthly"]["limit"], 30_000);
+}
+
+#[actix_web::test]
+async fn test_api_keys_and_tiers() {
+    setup_test_env();
+    let keys_file = std::env::temp_dir().join(format!("api-keys-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(&keys_file, json!({
+        "tiers": {
+            "basic": { "endpoints": ["/currency", "/v1"], "detailed_responses": false },
+            "pro": {}
+        },
+        "keys": {
+            "basic-key": { "tenant": "hobbyist", "tier": "basic" },
+            "pro-key": { "tenant": "acme", "tier": "pro" }
+        }
+    }).to_string()).unwrap();
+
+    let mut config = common::test_config(&common::start_fake_upstream());
+    config.auth_settings.api_keys_file = Some(keys_file.to_string_lossy().into_owned());
+    let app = test::init_service(build_test_app_with_config(&config)).await;
+    let _ = std::fs::remove_file(&keys_file);
+
+    let convert = |api_key: Option<&'static str>| {
+        let mut req = test::TestRequest::post()
+            .uri("/v1/currency")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(json!({ "from": "United States", "to": "Japan", "amount": 10.0 }).to_string());
+        if let Some(api_key) = api_key {
+            req = req.insert_header((API_KEY_HEADER, api_key));
+        }
+        test::call_service(&app, req.to_request())
+    };
+
+    let resp = convert(None).await;
+    assert_eq!(resp.status(), 401);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "UNAUTHORIZED");
+    assert!(body["request_id"].as_str().is_some_and(|id| !id.is_empty()));
+
+    // Basic keys get the simple response shape, pro keys the detailed one
+    let body: SimpleConversionResponse = test::read_body_json(convert(Some("basic-key")).await).await;
+    assert_eq!((body.from.as_str(), body.to.as_str(), body.amount), ("USD", "JPY", 1500.0));
+    let body: DetailedConversionResponse = test::read_body_json(convert(Some("pro-key")).await).await;
+    assert_eq!(body.data.to.amount, 1500.0);
+
+    // Neither tier includes the admin endpoints
+    let req = test::TestRequest::get()
+        .uri("/admin/cache")
+        .insert_header((API_KEY_HEADER, "pro-key"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
+
+#[actix_web::test]
+async fn test_admin_usage_stats_require_operator_key() {
+    setup_test_env();
+    let mut config = common::test_config(&common::start_fake_upstream());
+    config.auth_settings.admin_api_key = Some("operator-key".to_string());
+    let app = test::init_service(build_test_app_with_config(&config)).await;
+
+    for to in ["France", "Atlantis"] {
+        let req = test::TestRequest::post()
+            .uri("/v1/currency")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(json!({ "from": "United States", "to": to, "amount": 10.0 }).to_string())
+            .to_request();
+        test::call_service(&app, req).await;
+    }
+
+    let admin = |method: test::TestRequest, uri: &str, api_key: Option<&'static str>| {
+        let mut req = method.uri(uri);
+        if let Some(api_key) = api_key {
+            req = req.insert_header((API_KEY_HEADER, api_key));
+        }
+        req.to_request()
+    };
+    let resp = test::call_service(&app, admin(test::TestRequest::get(), "/admin/stats", None)).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = admin(test::TestRequest::get(), "/admin/stats", Some("operator-key"));
+    let stats: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(stats["total_requests"], 2);
+    assert_eq!(stats["successful_requests"], 1);
+    assert_eq!(stats["api_calls"], 1);
+    assert_eq!(stats["errors_by_kind"]["CountryNotFound"], 1);
+
+    // Rolling latency per route and per upstream dependency, over each window
+    let latency = &stats["latency"];
+    assert_eq!(latency["endpoints"]["/v1/currency"]["1m"]["count"], 2);
+    assert_eq!(latency["endpoints"]["/v1/currency"]["1h"]["count"], 2);
+    assert_eq!(latency["upstream"]["countries"]["5m"]["count"], 3);
+    assert_eq!(latency["upstream"]["rates"]["5m"]["count"], 1);
+    let rates = &latency["upstream"]["rates"]["1m"];
+    assert!(rates["p50_ms"].as_f64().unwrap() <= rates["max_ms"].as_f64().unwrap());
+
+    let req = admin(test::TestRequest::post(), "/admin/stats/reset", Some("operator-key"));
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+    let req = admin(test::TestRequest::get(), "/admin/stats", Some("operator-key"));
+    let stats: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(stats["total_requests"], 0);
+    assert_eq!(stats["latency"]["upstream"]["rates"]["1m"]["count"], 1);
+}
+
+#[actix_web::test]
+async fn test_metrics_endpoint() {
+    setup_test_env();
+    let app = test::init_service(build_test_app()).await;
+
+    for to in ["France", "Atlantis", "France"] {
+        let req = test::TestRequest::post()
+            .uri("/v1/currency")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(json!({ "from": "United States", "to": to, "amount": 10.0 }).to_string())
+            .to_request();
+        test::call_service(&app, req).await;
+    }
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "text/plain; version=0.0.4; charset=utf-8");
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+    // Requests are counted by route pattern, with the error code of failures
+    assert!(body.contains(r#"currency_converter_http_requests_total{route="/v1/currency",status="200",code=""} 2"#));
+    assert!(body.contains(r#"currency_converter_http_requests_total{route="/v1/currency",status="400",code="COUNTRY_NOT_FOUND"} 1"#));
+    assert!(body.contains(r#"currency_converter_http_request_duration_seconds_count{route="/v1/currency"} 3"#));
+    assert!(body.contains(r#"currency_converter_upstream_requests_total{provider="exchangerate-api",outcome="success"} 1"#));
+    // Country lookups count too, and an unknown country is still a successful call
+    assert!(body.contains(r#"currency_converter_upstream_requests_total{provider="restcountries",outcome="success"} 3"#));
+    assert!(body.contains(r#"currency_converter_cache_hits_total{cache="exchange_rates"} 1"#));
+    assert!(body.contains("# TYPE currency_converter_rate_limit_rejections_total counter"));
+}
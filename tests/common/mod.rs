@@ -0,0 +1,84 @@
+//! Local stand-in for restcountries.com and exchangerate-api.com used by integration tests.
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use currency_converter::config::{Config, UpstreamSettings};
+use serde_json::{json, Value};
+
+pub const VALID_API_KEY: &str = "test-api-key";
+
+/// USD-based rates served by the fake exchange rate API.
+const USD_RATES: &[(&str, f64)] = &[
+    ("USD", 1.0),
+    ("EUR", 0.9),
+    ("JPY", 150.0),
+    ("AUD", 1.5),
+];
+
+fn country(common: &str, code: &str, currency_name: &str, symbol: &str) -> Value {
+    json!({
+        "name": { "common": common, "official": format!("Official {}", common) },
+        "currencies": { code: { "name": currency_name, "symbol": symbol } }
+    })
+}
+
+async fn country_by_name(name: web::Path<String>) -> HttpResponse {
+    let found = match name.to_lowercase().as_str() {
+        "united states" => country("United States", "USD", "United States dollar", "$"),
+        "france" => country("France", "EUR", "Euro", "€"),
+        "japan" => country("Japan", "JPY", "Japanese yen", "¥"),
+        "australia" => country("Australia", "AUD", "Australian dollar", "$"),
+        _ => return HttpResponse::NotFound().json(json!({ "status": 404, "message": "Not Found" })),
+    };
+    HttpResponse::Ok().json(vec![found])
+}
+
+async fn latest_rates(path: web::Path<(String, String)>) -> HttpResponse {
+    let (api_key, base) = path.into_inner();
+    if api_key != VALID_API_KEY {
+        return HttpResponse::Forbidden().json(json!({ "result": "error", "error-type": "invalid-key" }));
+    }
+
+    let Some((_, pivot)) = USD_RATES.iter().find(|(code, _)| *code == base) else {
+        return HttpResponse::NotFound().json(json!({ "result": "error", "error-type": "unsupported-code" }));
+    };
+    let conversion_rates: serde_json::Map<String, Value> = USD_RATES
+        .iter()
+        .map(|(code, rate)| (code.to_string(), json!(rate / pivot)))
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "result": "success",
+        "base_code": base,
+        "time_last_update_utc": "Mon, 01 Jan 2024 00:00:01 +0000",
+        "conversion_rates": conversion_rates
+    }))
+}
+
+/// Starts the fake upstream on a random local port and returns its base URL.
+pub fn start_fake_upstream() -> String {
+    let server = HttpServer::new(|| {
+        App::new()
+            .route("/v3.1/name/{name}", web::get().to(country_by_name))
+            .route("/v6/{api_key}/latest/{base}", web::get().to(latest_rates))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .expect("Failed to bind fake upstream");
+
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    format!("http://{}", addr)
+}
+
+/// Configuration pointing every upstream at the fake server.
+pub fn test_config(base_url: &str) -> Config {
+    Config {
+        exchange_rate_api_key: VALID_API_KEY.to_string(),
+        upstream_settings: UpstreamSettings {
+            rest_countries_base_url: format!("{}/v3.1", base_url),
+            exchange_rate_api_base_url: format!("{}/v6", base_url),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
@@ -1,15 +1,30 @@
 use actix_web::{test, web};
-use currency_converter::health_check;
+use currency_converter::{app::build_app, config::Config, registry::ServiceRegistry};
+
+/// Builds the application as `main` serves it, without starting any upstream.
+fn build_test_app() -> actix_web::App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Response = actix_web::dev::ServiceResponse<actix_web::body::BoxBody>,
+        Config = (),
+        Error = actix_web::Error,
+        InitError = ()
+    >
+> {
+    let config = Config {
+        exchange_rate_api_key: "test_key".to_string(),
+        ..Default::default()
+    };
+    let registry = ServiceRegistry::new(&config).unwrap();
+    build_app(web::Data::new(registry), &config.client_rate_limit_settings)
+}
 
 #[actix_web::test]
 async fn test_health_check() {
-    let app = test::init_service(
-        actix_web::App::new()
-            .service(web::resource("/health").route(web::get().to(health_check)))
-    ).await;
+    let app = test::init_service(build_test_app()).await;
 
     let req = test::TestRequest::get()
-        .uri("/health")
+        .uri("/health/live")
         .to_request();
 
     let resp = test::call_service(&app, req).await;
@@ -17,4 +32,21 @@ async fn test_health_check() {
 
     let body = test::read_body(resp).await;
     assert_eq!(body, "OK");
-}
\ No newline at end of file
+}
+
+#[actix_web::test]
+async fn test_health_status_reports_circuit_breakers() {
+    let app = test::init_service(build_test_app()).await;
+
+    let req = test::TestRequest::get()
+        .uri("/health")
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "OK");
+    assert_eq!(body["circuit_breakers"]["restcountries"]["state"], "closed");
+    assert_eq!(body["circuit_breakers"]["exchangerate-api"]["state"], "closed");
+}